@@ -0,0 +1,153 @@
+use std::{fmt, io, path::{Path, PathBuf}, string::FromUtf8Error};
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    NotFound,
+    Io(io::Error),
+    UnexpectedEof,
+    InvalidUtf8,
+    Malformed(String),
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub path: Option<PathBuf>,
+    pub lump: Option<usize>,
+    pub offset: Option<u64>,
+    pub field: Option<&'static str>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            path: None,
+            lump: None,
+            offset: None,
+            field: None,
+        }
+    }
+
+    pub fn not_found(path: &Path) -> Self {
+        Self::new(ErrorKind::NotFound).with_path(path)
+    }
+
+    pub fn malformed(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Malformed(reason.into()))
+    }
+
+    pub fn unsupported(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Unsupported(reason.into()))
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.kind, ErrorKind::NotFound)
+    }
+
+    // The innermost context wins: a field named deep in a parser is not overwritten by its caller.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        self.path.get_or_insert_with(|| path.to_path_buf());
+        self
+    }
+
+    pub fn with_lump(mut self, lump: usize) -> Self {
+        self.lump.get_or_insert(lump);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset.get_or_insert(offset);
+        self
+    }
+
+    pub fn with_field(mut self, field: &'static str) -> Self {
+        self.field.get_or_insert(field);
+        self
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::new(ErrorKind::NotFound),
+            io::ErrorKind::UnexpectedEof => Self::new(ErrorKind::UnexpectedEof),
+            _ => Self::new(ErrorKind::Io(err)),
+        }
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(_: FromUtf8Error) -> Self {
+        Self::new(ErrorKind::InvalidUtf8)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::NotFound => write!(f, "file not found"),
+            ErrorKind::Io(err) => write!(f, "i/o error: {}", err),
+            ErrorKind::UnexpectedEof => write!(f, "unexpected end of data"),
+            ErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            ErrorKind::Malformed(reason) => write!(f, "malformed data: {}", reason),
+            ErrorKind::Unsupported(reason) => write!(f, "unsupported: {}", reason),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(field) = self.field {
+            write!(f, " reading `{}`", field)?;
+        }
+        if let Some(lump) = self.lump {
+            write!(f, " in lump {}", lump)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, " ({})", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub trait ResultExt<T> {
+    fn with_path(self, path: &Path) -> Result<T>;
+    fn with_lump(self, lump: usize) -> Result<T>;
+    fn with_offset(self, offset: u64) -> Result<T>;
+    fn with_field(self, field: &'static str) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn with_path(self, path: &Path) -> Result<T> {
+        self.map_err(|err| err.into().with_path(path))
+    }
+
+    fn with_lump(self, lump: usize) -> Result<T> {
+        self.map_err(|err| err.into().with_lump(lump))
+    }
+
+    fn with_offset(self, offset: u64) -> Result<T> {
+        self.map_err(|err| err.into().with_offset(offset))
+    }
+
+    fn with_field(self, field: &'static str) -> Result<T> {
+        self.map_err(|err| err.into().with_field(field))
+    }
+}
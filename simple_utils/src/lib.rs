@@ -1,52 +1,118 @@
 pub mod utils {
-    use std::{fs::File, io::{Seek, Read, SeekFrom}};
+    use std::{io::{Seek, Read, SeekFrom}, mem::size_of, path::Path};
+    use crate::error::{Error, ErrorKind, Result, ResultExt};
 
-    pub fn read_exact_from_file(f: &mut File, start: u64, size: usize) -> Option<Vec<u8>> {
-        f.seek(SeekFrom::Start(start)).ok()?;
+    pub fn read_exact_from_file<R: Read + Seek>(f: &mut R, start: u64, size: usize) -> Result<Vec<u8>> {
+        f.seek(SeekFrom::Start(start)).with_offset(start)?;
         let mut buf = vec![0; size];
-        f.read_exact(&mut buf).ok()?;
-        Some(buf)
+        f.read_exact(&mut buf).with_offset(start)?;
+        Ok(buf)
     }
 
-    pub fn read_segments_from_file(f: &mut File, start: u64, size_vec: &[usize]) -> Option<Vec<Vec<u8>>> {
-        f.seek(SeekFrom::Start(start)).ok()?;
+    pub fn read_segments_from_file<R: Read + Seek>(f: &mut R, start: u64, size_vec: &[usize]) -> Result<Vec<Vec<u8>>> {
+        f.seek(SeekFrom::Start(start)).with_offset(start)?;
         size_vec.iter().map(|&size| {
             let mut buf = vec![0; size];
-            f.read_exact(&mut buf).ok().map(|_| buf)
+            f.read_exact(&mut buf).with_offset(start)?;
+            Ok(buf)
         }).collect()
     }
 
+    pub fn get_slice(bytes: &[u8], start: usize, size: usize) -> Result<&[u8]> {
+        start.checked_add(size)
+            .and_then(|end| bytes.get(start..end))
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof).with_offset(start as u64))
+    }
+
     pub trait FromSlice: Sized {
-        fn from_u8_slice(slice: &[u8]) -> Option<Self>;
+        fn from_u8_slice(slice: &[u8]) -> Result<Self>;
+        fn from_be_u8_slice(slice: &[u8]) -> Result<Self>;
+
+        fn from_bytes_at(bytes: &[u8], ofs: usize) -> Result<Self> {
+            Self::from_u8_slice(get_slice(bytes, ofs, size_of::<Self>())?)
+        }
+
+        fn from_bytes_at_endian(bytes: &[u8], ofs: usize, big_endian: bool) -> Result<Self> {
+            let slice = get_slice(bytes, ofs, size_of::<Self>())?;
+            if big_endian { Self::from_be_u8_slice(slice) } else { Self::from_u8_slice(slice) }
+        }
     }
 
     macro_rules! impl_from_slice {
         ($($t:ty),*) => {
             $(
                 impl FromSlice for $t {
-                    fn from_u8_slice(slice: &[u8]) -> Option<Self> {
-                        Some(<$t>::from_le_bytes(slice.try_into().ok()?))
+                    fn from_u8_slice(slice: &[u8]) -> Result<Self> {
+                        let bytes = slice.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof))?;
+                        Ok(<$t>::from_le_bytes(bytes))
+                    }
+
+                    fn from_be_u8_slice(slice: &[u8]) -> Result<Self> {
+                        let bytes = slice.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof))?;
+                        Ok(<$t>::from_be_bytes(bytes))
                     }
                 }
             )*
         };
     }
     
-    impl_from_slice!(u16, i16, i32, f32);
+    impl_from_slice!(u16, i16, i32, u32, f32);
+
+    pub fn clean_path(path: &str) -> String {
+        path.replace('\\', "/").trim_start_matches('/').to_string()
+    }
 
-    pub fn null_term_str(f: &mut File, ofs: u64) -> Option<String> {
-        f.seek(SeekFrom::Start(ofs)).ok();
+    pub fn normalize_name(name: &str) -> String {
+        clean_path(name).to_lowercase()
+    }
+
+    // `rel_path` cleaned for joining onto an output or content folder. Names come from archives and
+    // map entities, so `..`, rooted paths and drive prefixes that would leave the folder are refused.
+    pub fn safe_rel_path(rel_path: &str) -> Result<String> {
+        let cleaned = clean_path(rel_path);
+        let escapes = rel_path.starts_with(['/', '\\'])
+            || cleaned.split('/').next().is_some_and(|first| first.contains(':'))
+            || cleaned.split('/').any(|part| part == "..");
+        if escapes {
+            return Err(Error::unsupported("path leaves its folder").with_path(Path::new(rel_path)));
+        }
+        Ok(cleaned)
+    }
+
+    pub fn json_escape(s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len() + 2);
+        escaped.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+
+    pub fn null_term_str<R: Read + Seek>(f: &mut R, ofs: u64) -> Result<String> {
+        f.seek(SeekFrom::Start(ofs)).with_offset(ofs)?;
         let mut buf = Vec::new();
 
         loop {
             let mut byte = [0u8; 1];
-            let count_readed = f.read(&mut byte).ok()?;
+            let count_readed = f.read(&mut byte).with_offset(ofs)?;
             if count_readed == 0 || byte[0] == 0 {
                 break;
             }
             buf.push(byte[0]);
         }
 
-        Some(String::from_utf8(buf).ok()?)
+        String::from_utf8(buf).with_offset(ofs)
     }
-}
\ No newline at end of file
+}
+
+pub mod error;
+pub mod zip;
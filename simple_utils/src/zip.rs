@@ -0,0 +1,252 @@
+use std::{fs, io::{Read, Write}, path::Path, collections::HashMap};
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use crate::error::{Error, Result, ResultExt};
+use crate::utils::{FromSlice, get_slice, safe_rel_path};
+pub use crate::utils::normalize_name;
+
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+const CENTRAL_SIGNATURE: u32 = 0x02014b50;
+const LOCAL_SIGNATURE: u32 = 0x04034b50;
+const EOCD_SIZE: usize = 22;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const LOCAL_HEADER_SIZE: usize = 30;
+const MAX_COMMENT_SIZE: usize = u16::MAX as usize;
+const LZMA_PROPS_SIZE: usize = 5;
+
+pub const METHOD_STORED: u16 = 0;
+pub const METHOD_DEFLATE: u16 = 8;
+pub const METHOD_LZMA: u16 = 14;
+
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    pub method: u16,
+    pub crc32: u32,
+    pub compressed_size: u32,
+    pub size: u32,
+    local_header_ofs: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct ZipArchive {
+    data: Vec<u8>,
+    entries: Vec<ZipEntry>,
+    // Normalized name to the first entry stored under it.
+    index: HashMap<String, usize>,
+}
+
+impl ZipArchive {
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        let search_start = data.len().saturating_sub(EOCD_SIZE + MAX_COMMENT_SIZE);
+        let search_end = data.len().checked_sub(EOCD_SIZE).ok_or_else(|| Error::malformed("zip is too small"))?;
+        let eocd_ofs = (search_start..=search_end)
+            .rev()
+            .find(|&ofs| u32::from_bytes_at(&data, ofs).ok() == Some(EOCD_SIGNATURE))
+            .ok_or_else(|| Error::malformed("zip end of central directory not found"))?;
+        let entry_count = u16::from_bytes_at(&data, eocd_ofs + 10).with_field("entry_count")?;
+        let central_size = u32::from_bytes_at(&data, eocd_ofs + 12).with_field("central_size")? as usize;
+        let central_ofs = u32::from_bytes_at(&data, eocd_ofs + 16).with_field("central_ofs")? as usize;
+
+        // Pakfiles written by some tools store offsets relative to the BSP instead of the lump,
+        // so trust the end of central directory position over the stored offset.
+        let shift = (central_ofs + central_size) as i64 - eocd_ofs as i64;
+        let mut ofs = eocd_ofs.checked_sub(central_size).ok_or_else(|| Error::malformed("zip central directory out of bounds"))?;
+        let mut entries = Vec::with_capacity(entry_count as usize);
+
+        for _ in 0..entry_count {
+            if u32::from_bytes_at(&data, ofs)? != CENTRAL_SIGNATURE {
+                return Err(Error::malformed("bad zip central directory signature").with_offset(ofs as u64));
+            }
+            let name_len = u16::from_bytes_at(&data, ofs + 28).with_field("name_len")? as usize;
+            let extra_len = u16::from_bytes_at(&data, ofs + 30).with_field("extra_len")? as usize;
+            let comment_len = u16::from_bytes_at(&data, ofs + 32).with_field("comment_len")? as usize;
+            let name_bytes = get_slice(&data, ofs + CENTRAL_HEADER_SIZE, name_len).with_field("name")?;
+            let local_header_ofs = (u32::from_bytes_at(&data, ofs + 42).with_field("local_header_ofs")? as i64 - shift)
+                .try_into()
+                .map_err(|_| Error::malformed("zip local header offset out of bounds").with_offset(ofs as u64))?;
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name_bytes).into_owned(),
+                method: u16::from_bytes_at(&data, ofs + 10).with_field("method")?,
+                crc32: u32::from_bytes_at(&data, ofs + 16).with_field("crc32")?,
+                compressed_size: u32::from_bytes_at(&data, ofs + 20).with_field("compressed_size")?,
+                size: u32::from_bytes_at(&data, ofs + 24).with_field("size")?,
+                local_header_ofs,
+            });
+            ofs += CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+        }
+
+        let mut index = HashMap::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            index.entry(normalize_name(&entry.name)).or_insert(i);
+        }
+        Ok(Self { data, entries, index })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::new(fs::read(path).with_path(path)?).with_path(path)
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ZipEntry> {
+        self.index.get(&normalize_name(name)).map(|&i| &self.entries[i])
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    pub fn read(&self, entry: &ZipEntry) -> Result<Vec<u8>> {
+        let ofs = entry.local_header_ofs as usize;
+        if u32::from_bytes_at(&self.data, ofs)? != LOCAL_SIGNATURE {
+            return Err(Error::malformed(format!("bad zip local header signature for {}", entry.name)).with_offset(ofs as u64));
+        }
+        let name_len = u16::from_bytes_at(&self.data, ofs + 26).with_field("name_len")? as usize;
+        let extra_len = u16::from_bytes_at(&self.data, ofs + 28).with_field("extra_len")? as usize;
+        let data_ofs = ofs + LOCAL_HEADER_SIZE + name_len + extra_len;
+        let compressed = get_slice(&self.data, data_ofs, entry.compressed_size as usize).with_field("file_data")?;
+
+        match entry.method {
+            METHOD_STORED => Ok(compressed.to_vec()),
+            METHOD_DEFLATE => {
+                let mut output = Vec::with_capacity(entry.size as usize);
+                DeflateDecoder::new(compressed).read_to_end(&mut output).with_offset(data_ofs as u64)?;
+                Ok(output)
+            }
+            METHOD_LZMA => {
+                // 2 bytes of LZMA SDK version, 2 bytes of properties size, then the properties.
+                let props_size = u16::from_bytes_at(compressed, 2).with_field("lzma_props_size")? as usize;
+                let props = get_slice(compressed, 4, LZMA_PROPS_SIZE).with_field("lzma_props")?;
+                let stream = compressed.get((4 + props_size)..).unwrap_or_default();
+                lzma_decompress(props, entry.size as u64, stream).with_offset(data_ofs as u64)
+            }
+            method => Err(Error::unsupported(format!("zip compression method {} for {}", method, entry.name))),
+        }
+    }
+
+    pub fn read_by_name(&self, name: &str) -> Result<Vec<u8>> {
+        let entry = self.find(name).ok_or_else(|| Error::not_found(Path::new(name)))?;
+        self.read(entry)
+    }
+
+    pub fn extract(&self, entry: &ZipEntry, output_path: &Path) -> Result<()> {
+        let output_file_path = output_path.join(safe_rel_path(&entry.name)?.to_lowercase());
+        if let Some(parent_dir) = output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        fs::write(&output_file_path, self.read(entry)?).with_path(&output_file_path)
+    }
+
+    pub fn extract_all(&self, output_path: &Path) -> Result<usize> {
+        self.entries.iter().try_fold(0, |count, entry| {
+            self.extract(entry, output_path)?;
+            Ok(count + 1)
+        })
+    }
+}
+
+/// Decodes a raw LZMA stream given its 5 property bytes and the unpacked size.
+pub fn lzma_decompress(props: &[u8], size: u64, stream: &[u8]) -> Result<Vec<u8>> {
+    let mut lzma_buf = Vec::with_capacity(LZMA_PROPS_SIZE + 8 + stream.len());
+    lzma_buf.extend_from_slice(get_slice(props, 0, LZMA_PROPS_SIZE).with_field("lzma_props")?);
+    lzma_buf.extend_from_slice(&size.to_le_bytes());
+    lzma_buf.extend_from_slice(stream);
+    let mut output = Vec::with_capacity(size as usize);
+    lzma_rs::lzma_decompress(&mut lzma_buf.as_slice(), &mut output)
+        .map_err(|err| Error::malformed(format!("lzma: {}", err)))?;
+    Ok(output)
+}
+
+#[derive(Debug, Default)]
+pub struct ZipWriter {
+    data: Vec<u8>,
+    central: Vec<u8>,
+    count: u16,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        self.add_entry(name, contents, contents, METHOD_STORED)
+    }
+
+    // Falls back to storing the file when deflate would not make it smaller.
+    pub fn add_file_deflated(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents)?;
+        let compressed = encoder.finish()?;
+
+        if compressed.len() >= contents.len() {
+            self.add_file(name, contents)
+        } else {
+            self.add_entry(name, contents, &compressed, METHOD_DEFLATE)
+        }
+    }
+
+    fn add_entry(&mut self, name: &str, contents: &[u8], compressed: &[u8], method: u16) -> Result<()> {
+        let name = normalize_name(name);
+        check_limit(self.count as usize + 1, u16::MAX as usize, "entry_count")?;
+        check_limit(name.len(), u16::MAX as usize + 1, "name_len")?;
+        let local_header_ofs = check_limit(self.data.len(), u32::MAX as usize, "local_header_ofs")? as u32;
+        let size = check_limit(contents.len(), u32::MAX as usize, "size")? as u32;
+        let compressed_size = check_limit(compressed.len(), u32::MAX as usize, "compressed_size")? as u32;
+        let crc32 = crc32fast::hash(contents);
+        let version_needed: u16 = if method == METHOD_DEFLATE { 20 } else { 10 };
+
+        self.data.extend_from_slice(&LOCAL_SIGNATURE.to_le_bytes());
+        self.data.extend_from_slice(&version_needed.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        self.data.extend_from_slice(&method.to_le_bytes());
+        self.data.extend_from_slice(&[0; 4]);
+        self.data.extend_from_slice(&crc32.to_le_bytes());
+        self.data.extend_from_slice(&compressed_size.to_le_bytes());
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(compressed);
+
+        self.central.extend_from_slice(&CENTRAL_SIGNATURE.to_le_bytes());
+        self.central.extend_from_slice(&20u16.to_le_bytes());
+        self.central.extend_from_slice(&version_needed.to_le_bytes());
+        self.central.extend_from_slice(&0u16.to_le_bytes());
+        self.central.extend_from_slice(&method.to_le_bytes());
+        self.central.extend_from_slice(&[0; 4]);
+        self.central.extend_from_slice(&crc32.to_le_bytes());
+        self.central.extend_from_slice(&compressed_size.to_le_bytes());
+        self.central.extend_from_slice(&size.to_le_bytes());
+        self.central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        self.central.extend_from_slice(&[0; 12]);
+        self.central.extend_from_slice(&local_header_ofs.to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        let central_ofs = check_limit(self.data.len(), u32::MAX as usize, "central_ofs")? as u32;
+        let central_size = check_limit(self.central.len(), u32::MAX as usize, "central_size")? as u32;
+        self.data.append(&mut self.central);
+        self.data.extend_from_slice(&EOCD_SIGNATURE.to_le_bytes());
+        self.data.extend_from_slice(&[0; 4]);
+        self.data.extend_from_slice(&self.count.to_le_bytes());
+        self.data.extend_from_slice(&self.count.to_le_bytes());
+        self.data.extend_from_slice(&central_size.to_le_bytes());
+        self.data.extend_from_slice(&central_ofs.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        Ok(self.data)
+    }
+}
+
+// Values from `max` up need Zip64 records (the maximum itself marks one), which are not written.
+fn check_limit(value: usize, max: usize, field: &'static str) -> Result<usize> {
+    if value >= max {
+        return Err(Error::unsupported(format!("{} is past the zip limit of {} and Zip64 is not supported", value, max - 1)).with_field(field));
+    }
+    Ok(value)
+}
@@ -0,0 +1,186 @@
+use std::borrow::Cow;
+use simple_utils::error::{Error, Result};
+
+// One `{ "key" "value" ... }` block of the entity lump. Keys and values are kept as raw bytes so
+// maps saved with a non-UTF-8 codepage survive a round trip; the accessors decode them lossily.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entity {
+    // Byte offset of the opening `{` inside the entity lump.
+    pub offset: usize,
+    pub pairs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Entity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Keys are matched case-insensitively, like the engine does; the first occurrence wins.
+    pub fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        self.get_all(key).next()
+    }
+
+    // Every value of a repeated key, in lump order. Entity I/O outputs such as `OnTrigger` repeat.
+    pub fn get_all(&self, key: &str) -> impl Iterator<Item = Cow<'_, str>> + use<'_> {
+        let key = key.as_bytes().to_vec();
+        self.pairs
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(&key))
+            .map(|(_, v)| String::from_utf8_lossy(v))
+    }
+
+    pub fn classname(&self) -> Cow<'_, str> {
+        self.get("classname").unwrap_or_default()
+    }
+
+    pub fn pairs(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+        self.pairs.iter().map(|(k, v)| (String::from_utf8_lossy(k), String::from_utf8_lossy(v)))
+    }
+
+    // Replaces the first value of `key` and drops its repeats, or appends the key when it is missing.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let mut found = false;
+        self.pairs.retain_mut(|(k, v)| {
+            if !k.eq_ignore_ascii_case(key.as_bytes()) {
+                return true;
+            }
+            if found {
+                return false;
+            }
+            found = true;
+            *v = value.as_bytes().to_vec();
+            true
+        });
+        if !found {
+            self.add(key, value);
+        }
+    }
+
+    // Appends a pair even if the key already exists, which is how outputs are added.
+    pub fn add(&mut self, key: &str, value: &str) {
+        self.pairs.push((key.as_bytes().to_vec(), value.as_bytes().to_vec()));
+    }
+
+    // Returns how many pairs were removed.
+    pub fn remove(&mut self, key: &str) -> usize {
+        let count = self.pairs.len();
+        self.pairs.retain(|(k, _)| !k.eq_ignore_ascii_case(key.as_bytes()));
+        count - self.pairs.len()
+    }
+}
+
+enum Token {
+    Open,
+    Close,
+    Str(Vec<u8>),
+}
+
+struct Tokenizer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Tokenizer<'_> {
+    // Returns the token and the offset it starts at.
+    fn next_token(&mut self) -> Result<Option<(Token, usize)>> {
+        while self.pos < self.data.len() && (self.data[self.pos].is_ascii_whitespace() || self.data[self.pos] == 0) {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        let c = match self.data.get(self.pos) {
+            Some(&c) => c,
+            None => return Ok(None),
+        };
+        self.pos += 1;
+
+        let token = match c {
+            b'{' => Token::Open,
+            b'}' => Token::Close,
+            b'"' => {
+                let mut token = Vec::new();
+                loop {
+                    match self.data.get(self.pos) {
+                        None => return Err(Error::malformed("unterminated string in entity lump").with_offset(start as u64)),
+                        Some(b'"') => break,
+                        // Compilers never write quotes into values, but some editors escape them this way.
+                        Some(b'\\') if self.data.get(self.pos + 1) == Some(&b'"') => {
+                            token.push(b'"');
+                            self.pos += 1;
+                        }
+                        Some(&c) => token.push(c),
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                Token::Str(token)
+            }
+            _ => {
+                while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() && !matches!(self.data[self.pos], b'"' | b'{' | b'}' | 0) {
+                    self.pos += 1;
+                }
+                Token::Str(self.data[start..self.pos].to_vec())
+            }
+        };
+        Ok(Some((token, start)))
+    }
+}
+
+pub fn parse_entities(data: &[u8]) -> Result<Vec<Entity>> {
+    let mut tokenizer = Tokenizer { data, pos: 0 };
+    let mut entities = Vec::new();
+
+    while let Some((token, ofs)) = tokenizer.next_token()? {
+        if !matches!(token, Token::Open) {
+            return Err(Error::malformed("expected `{` in entity lump").with_offset(ofs as u64));
+        }
+        let mut entity = Entity { offset: ofs, pairs: Vec::new() };
+
+        loop {
+            let key = match tokenizer.next_token()? {
+                Some((Token::Close, _)) => break,
+                Some((Token::Str(key), _)) => key,
+                Some((Token::Open, ofs)) => return Err(Error::malformed("unexpected `{` in entity").with_offset(ofs as u64)),
+                None => return Err(Error::malformed("unterminated entity").with_offset(entity.offset as u64)),
+            };
+            let value = match tokenizer.next_token()? {
+                Some((Token::Str(value), _)) => value,
+                Some((_, ofs)) => return Err(Error::malformed("entity key has no value").with_offset(ofs as u64)),
+                None => return Err(Error::malformed("unterminated entity").with_offset(entity.offset as u64)),
+            };
+            entity.pairs.push((key, value));
+        }
+
+        entities.push(entity);
+    }
+
+    Ok(entities)
+}
+
+// The engine's parser has no escapes, so a quote inside a token cannot be written at all.
+fn push_quoted(out: &mut Vec<u8>, token: &[u8], entity: &Entity) -> Result<()> {
+    if token.contains(&b'"') {
+        let token = String::from_utf8_lossy(token);
+        return Err(Error::unsupported(format!("entity key or value contains a quote: {}", token)).with_offset(entity.offset as u64));
+    }
+    out.push(b'"');
+    out.extend_from_slice(token);
+    out.push(b'"');
+    Ok(())
+}
+
+// Writes entities the way vbsp does, which is also the .ent format used by Stripper and BSPSource.
+// The entity lump itself additionally ends with a null byte.
+pub fn serialize_entities(entities: &[Entity]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for entity in entities {
+        out.extend_from_slice(b"{\n");
+        for (key, value) in &entity.pairs {
+            push_quoted(&mut out, key, entity)?;
+            out.push(b' ');
+            push_quoted(&mut out, value, entity)?;
+            out.push(b'\n');
+        }
+        out.extend_from_slice(b"}\n");
+    }
+    Ok(out)
+}
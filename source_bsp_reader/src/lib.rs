@@ -1,10 +1,22 @@
 pub mod reader {
-    use std::{fs, fs::File, path::{Path, PathBuf}, mem::size_of, collections::{HashMap, HashSet}};
-    use regex::Regex;
-    use simple_utils::utils::{read_exact_from_file, read_segments_from_file, FromSlice};
-    use source_mdl_mats_finder::finder::{TexturesInfo, VMTInfo};
+    use std::{fs, fs::File, io::{Cursor, Read, Seek}, path::Path, mem::size_of, collections::{HashMap, HashSet}};
+    use simple_utils::utils::{read_exact_from_file, read_segments_from_file, get_slice, FromSlice};
+    use simple_utils::error::{Error, ErrorKind, Result, ResultExt};
+    pub use simple_utils::zip::{ZipArchive, ZipEntry};
+    use simple_utils::zip::{ZipWriter, lzma_decompress, normalize_name};
+    use source_mdl_mats_finder::finder::{resolve_material, resolve_model_skins};
+    pub use source_mdl_mats_finder::gma::GmaArchive;
+    pub use source_mdl_mats_finder::search::SearchPaths;
+    pub use source_mdl_mats_finder::vfs::FileSystem;
+    pub use source_mdl_mats_finder::baseline::Baseline;
+    pub use source_mdl_mats_finder::content::{AssetKind, ContentSet, Referrer, ResolveContext};
+    use source_mdl_mats_finder::fastdl::{FastDlWriter, build_res_file};
+    use source_mdl_mats_finder::lua::build_resource_lua;
+    pub use source_mdl_mats_finder::output::{OutputOptions, ContentWriter, DirWriter, OutputFormat, create_writer};
+    pub use crate::entities::{Entity, parse_entities, serialize_entities};
+    pub use crate::static_props::{StaticPropLump, StaticPropLumpT, parse_static_prop_names, parse_static_props};
 
-    const HEADER_SIZE: usize = size_of::<DHeaderT>();
+    const HEADER_SIZE: usize = I32_SIZE * 3 + LUMP_SIZE * HEADER_LUMPS;
     const LUMP_SIZE: usize = size_of::<LumpT>();
     const DGAME_LUMP_SIZE: usize = size_of::<DGameLumpT>();
     const DMODEL_SIZE: usize = size_of::<DModelT>();
@@ -13,7 +25,30 @@ pub mod reader {
     const F32_SIZE: usize = size_of::<f32>();
     const U16_SIZE: usize = size_of::<u16>();
     const HEADER_LUMPS: usize = 64;
-    const PS_NAME_SIZE: usize = 128;
+    const VBSP_IDENT: &[u8; I32_SIZE] = b"VBSP";
+    // Xbox 360 and PS3 maps store every header field big-endian, which also flips the ident.
+    const PSBV_IDENT: &[u8; I32_SIZE] = b"PSBV";
+    const L4D2_VERSION: i32 = 21;
+    const ENTITY_LUMP: usize = 0;
+    const GAME_LUMP: usize = 35;
+    const PAKFILE_LUMP: usize = 40;
+    const LZMA_ID: &[u8; I32_SIZE] = b"LZMA";
+    const LZMA_HEADER_SIZE: usize = I32_SIZE * 3 + 5;
+    const GAME_LUMP_COMPRESSED: u16 = 0x0001;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PakfileMode {
+        Skip,
+        Extract,
+    }
+
+    // Which model skins get their materials collected: only those set by entities and static
+    // props, or all of them for maps that switch skins at runtime.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SkinMode {
+        Used,
+        All,
+    }
 
     #[derive(Debug)]
     pub struct LumpT {
@@ -23,23 +58,37 @@ pub mod reader {
         four_cc: [u8; I32_SIZE],
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
     pub struct Vector {
-        x: f32,
-        y: f32,
-        z: f32,
+        pub(crate) x: f32,
+        pub(crate) y: f32,
+        pub(crate) z: f32,
     }
 
     impl Vector {
-        fn from_u8_vec(u8_vec: &[u8]) -> Option<Self> {
-            Some(Self {
-                x: f32::from_u8_slice(u8_vec.get(0..F32_SIZE)?)?,
-                y: f32::from_u8_slice(u8_vec.get(F32_SIZE..(F32_SIZE * 2))?)?,
-                z: f32::from_u8_slice(u8_vec.get((F32_SIZE * 2)..(F32_SIZE * 3))?)?,
+        pub fn x(&self) -> f32 {
+            self.x
+        }
+
+        pub fn y(&self) -> f32 {
+            self.y
+        }
+
+        pub fn z(&self) -> f32 {
+            self.z
+        }
+
+        fn from_u8_vec(u8_vec: &[u8], big_endian: bool) -> Result<Self> {
+            Ok(Self {
+                x: f32::from_bytes_at_endian(u8_vec, 0, big_endian).with_field("x")?,
+                y: f32::from_bytes_at_endian(u8_vec, F32_SIZE, big_endian).with_field("y")?,
+                z: f32::from_bytes_at_endian(u8_vec, F32_SIZE * 2, big_endian).with_field("z")?,
             })
         }
     }
 
+    // Mirrors the on-disk struct; not every field is read yet.
+    #[allow(dead_code)]
     #[derive(Debug)]
     pub struct DModelT {
         mins: Vector,
@@ -50,6 +99,8 @@ pub mod reader {
         numfaces: i32,
     }    
 
+    // Mirrors the on-disk struct; not every field is read yet.
+    #[allow(dead_code)]
     #[derive(Debug)]
     pub struct DGameLumpT {
         id: i32,
@@ -59,238 +110,638 @@ pub mod reader {
         file_len: i32,
     }
 
+    trait ReadSeek: Read + Seek {}
+    impl<T: Read + Seek> ReadSeek for T {}
+
     #[derive(Debug)]
     pub struct DHeaderT {
         path: String,
+        // Set when the map was read out of an archive rather than opened from disk.
+        bytes: Option<Vec<u8>>,
+        // Set once the entities were edited; replaces the entity lump on read and write.
+        entities: Option<Vec<Entity>>,
         ident: [u8; I32_SIZE],
         version: i32,
         lumps: [LumpT; HEADER_LUMPS],
         map_revision: i32,
+        big_endian: bool,
+        // Left 4 Dead 2 stores lump_t as version, fileofs, filelen, fourCC.
+        l4d2_lumps: bool,
     }
 
-    fn get_bytes_4(bytes: &[u8], start: usize) -> Option<[u8; I32_SIZE]> {
-        bytes[start..start + I32_SIZE].try_into().ok()
+    fn get_bytes_4(bytes: &[u8], start: usize) -> Result<[u8; I32_SIZE]> {
+        get_slice(bytes, start, I32_SIZE)?
+            .try_into()
+            .map_err(|_| Error::new(ErrorKind::UnexpectedEof).with_offset(start as u64))
     }
 
     impl LumpT {
-        fn new(header_bytes: &[u8], lump_num: usize) -> Option<Self> {
+        fn new(header_bytes: &[u8], lump_num: usize, big_endian: bool, l4d2_lumps: bool) -> Result<Self> {
             let offset = I32_SIZE * 2 + lump_num * LUMP_SIZE;
-            Some(Self {
-                file_ofs: i32::from_u8_slice(header_bytes.get(offset..(offset + I32_SIZE))?)?,
-                file_len: i32::from_u8_slice(header_bytes.get((offset + I32_SIZE)..(offset + I32_SIZE * 2))?)?,
-                version: i32::from_u8_slice(header_bytes.get((offset + I32_SIZE * 2)..(offset + I32_SIZE * 3))?)?,
-                four_cc: get_bytes_4(header_bytes, offset + I32_SIZE * 3)?,
+            let (file_ofs_pos, file_len_pos, version_pos) = if l4d2_lumps {
+                (offset + I32_SIZE, offset + I32_SIZE * 2, offset)
+            } else {
+                (offset, offset + I32_SIZE, offset + I32_SIZE * 2)
+            };
+            Ok(Self {
+                file_ofs: i32::from_bytes_at_endian(header_bytes, file_ofs_pos, big_endian).with_field("fileofs")?,
+                file_len: i32::from_bytes_at_endian(header_bytes, file_len_pos, big_endian).with_field("filelen")?,
+                version: i32::from_bytes_at_endian(header_bytes, version_pos, big_endian).with_field("version")?,
+                four_cc: get_bytes_4(header_bytes, offset + I32_SIZE * 3).with_field("fourCC")?,
             })
         }
     }
 
+    // lzma_header_t: id, actualSize, lzmaSize and the 5 property bytes, followed by the raw stream.
+    fn decompress_lzma(data: &[u8]) -> Result<Vec<u8>> {
+        let actual_size = u32::from_bytes_at(data, I32_SIZE).with_field("actualSize")?;
+        let lzma_size = u32::from_bytes_at(data, I32_SIZE * 2).with_field("lzmaSize")?;
+        let props = get_slice(data, I32_SIZE * 3, 5).with_field("properties")?;
+        let stream = get_slice(data, LZMA_HEADER_SIZE, lzma_size as usize).with_field("lzma_data")?;
+        let output = lzma_decompress(props, actual_size as u64, stream)?;
+        if output.len() != actual_size as usize {
+            return Err(Error::malformed("LZMA lump decompressed to the wrong size"));
+        }
+        Ok(output)
+    }
+
+    fn is_lzma(data: &[u8]) -> bool {
+        data.starts_with(LZMA_ID)
+    }
+
+    fn relocate_game_lump(lump_data: &mut [u8], delta: i32) -> Result<()> {
+        let lump_count = i32::from_bytes_at(lump_data, 0).with_field("lumpCount")?;
+        for i in 0..lump_count.max(0) as usize {
+            let ofs_pos = I32_SIZE + DGAME_LUMP_SIZE * i + I32_SIZE + U16_SIZE * 2;
+            let file_ofs = i32::from_bytes_at(lump_data, ofs_pos).with_field("fileofs")?;
+            if file_ofs != 0 {
+                lump_data[ofs_pos..(ofs_pos + I32_SIZE)].copy_from_slice(&(file_ofs + delta).to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    // The map's pakfile mounted in front of the search paths, as the engine does, so packed files
+    // win over loose copies at every depth of the dependency walk.
+    #[derive(Debug)]
+    struct PakfileLayer<'a> {
+        pakfile: &'a ZipArchive,
+        file_system: &'a dyn FileSystem,
+    }
+
+    impl PakfileLayer<'_> {
+        fn find_layer(&self, rel_path: &str) -> &dyn FileSystem {
+            if self.pakfile.exists(rel_path) {
+                self.pakfile
+            } else {
+                self.file_system
+            }
+        }
+    }
+
+    impl FileSystem for PakfileLayer<'_> {
+        fn describe(&self) -> String {
+            self.file_system.describe()
+        }
+
+        fn locate(&self, rel_path: &str) -> Option<String> {
+            if self.pakfile.exists(rel_path) {
+                Some("pakfile".to_string())
+            } else {
+                self.file_system.locate(rel_path)
+            }
+        }
+
+        fn exists(&self, rel_path: &str) -> bool {
+            self.pakfile.exists(rel_path) || self.file_system.exists(rel_path)
+        }
+
+        fn size(&self, rel_path: &str) -> Option<u64> {
+            self.find_layer(rel_path).size(rel_path)
+        }
+
+        fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+            self.find_layer(rel_path).read(rel_path)
+        }
+
+        fn list(&self, rel_dir: &str) -> Vec<String> {
+            let mut seen = HashSet::new();
+            let mut files = self.pakfile.list(rel_dir);
+            files.extend(self.file_system.list(rel_dir));
+            files.retain(|path| seen.insert(normalize_name(path)));
+            files
+        }
+
+        fn find_name(&self, rel_path: &str) -> Option<String> {
+            self.pakfile.find_name(rel_path).or_else(|| self.file_system.find_name(rel_path))
+        }
+
+        fn open(&self, rel_path: &str) -> Result<Box<dyn Read + '_>> {
+            self.find_layer(rel_path).open(rel_path)
+        }
+    }
+
     impl DHeaderT {
-        pub fn new(path: &Path) -> Option<Self> {
-            let mut f = File::open(path).ok()?;
-            let header_bytes = read_exact_from_file(&mut f, 0, HEADER_SIZE)?;
+        pub fn new(path: &Path) -> Result<Self> {
+            let mut f = File::open(path).with_path(path)?;
+            Self::parse_header(&mut f, path)
+        }
+
+        pub fn from_bytes(bytes: Vec<u8>, path: &Path) -> Result<Self> {
+            Self::parse_header(&mut Cursor::new(&bytes), path)
+                .map(|dheader_t| Self { bytes: Some(bytes), ..dheader_t })
+        }
+
+        // Every map packed under maps/ in a Garry's Mod addon, sorted; from_gma loads the first.
+        pub fn maps_in_gma(gma: &GmaArchive) -> Vec<&str> {
+            let mut bsp_paths: Vec<&str> = gma.entries()
+                .map(|entry| entry.path.as_str())
+                .filter(|path| {
+                    let path = normalize_name(path);
+                    path.starts_with("maps/") && path.ends_with(".bsp")
+                })
+                .collect();
+            bsp_paths.sort();
+            bsp_paths
+        }
+
+        pub fn from_gma(gma: &GmaArchive) -> Result<Self> {
+            let bsp_paths = Self::maps_in_gma(gma);
+            let bsp_path = bsp_paths.first().ok_or_else(|| Error::not_found(&gma.path().join("maps/*.bsp")))?;
+            Self::from_bytes(gma.read(bsp_path)?, &gma.path().join(bsp_path))
+        }
+
+        fn parse_header<R: Read + Seek>(f: &mut R, path: &Path) -> Result<Self> {
+            let header_bytes = read_exact_from_file(f, 0, HEADER_SIZE).with_field("dheader_t").with_path(path)?;
+            let ident = get_bytes_4(&header_bytes, 0).with_field("ident").with_path(path)?;
+            let big_endian = match &ident {
+                VBSP_IDENT => false,
+                PSBV_IDENT => true,
+                _ => return Err(Error::malformed("not a VBSP map").with_field("ident").with_path(path)),
+            };
+            let version = i32::from_bytes_at_endian(&header_bytes, I32_SIZE, big_endian).with_field("version").with_path(path)?;
+
+            // v21 is shared by L4D2 and CS:GO. Lump data can never start inside the header, so a first
+            // lump_t field below the header size is L4D2's small version number, provided the field
+            // after it is a real file offset.
+            let l4d2_lumps = version == L4D2_VERSION && {
+                let first = i32::from_bytes_at_endian(&header_bytes, I32_SIZE * 2, big_endian).with_lump(0).with_path(path)?;
+                let second = i32::from_bytes_at_endian(&header_bytes, I32_SIZE * 3, big_endian).with_lump(0).with_path(path)?;
+                (0..HEADER_SIZE as i32).contains(&first) && second >= HEADER_SIZE as i32
+            };
+
             let map_revision_ofs = I32_SIZE * 2 + LUMP_SIZE * HEADER_LUMPS;
-            Some(Self {
-                path: String::from(path.to_str()?),
-                ident: get_bytes_4(&header_bytes, 0)?,
-                version: i32::from_u8_slice(header_bytes.get(0..I32_SIZE)?)?,
-                map_revision: i32::from_u8_slice(header_bytes.get(map_revision_ofs..(map_revision_ofs + I32_SIZE))?)?,
-                lumps: std::array::from_fn(|i| {
-                    match LumpT::new(&header_bytes, i) {
-                        Some(r) => r,
-                        None => {
-                            LumpT {
-                                file_ofs: 0,
-                                file_len: 0,
-                                version: 0,
-                                four_cc: [0; I32_SIZE],
-                            }
-                        }
-                    }
-                }),
+            let lumps = (0..HEADER_LUMPS)
+                .map(|i| LumpT::new(&header_bytes, i, big_endian, l4d2_lumps).with_lump(i))
+                .collect::<Result<Vec<LumpT>>>()
+                .with_path(path)?;
+            Ok(Self {
+                path: path.to_str()
+                    .map(String::from)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidUtf8).with_path(path))?,
+                bytes: None,
+                entities: None,
+                ident,
+                version,
+                map_revision: i32::from_bytes_at_endian(&header_bytes, map_revision_ofs, big_endian).with_field("mapRevision").with_path(path)?,
+                lumps: lumps.try_into().map_err(|_| Error::malformed("wrong lump count").with_path(path))?,
+                big_endian,
+                l4d2_lumps,
             })
         }
 
-        pub fn get_lump_info(&self, lump_id: usize) -> Option<&LumpT> {
-            Some(&self.lumps[lump_id])
+        pub fn ident(&self) -> &[u8; I32_SIZE] {
+            &self.ident
         }
 
-        pub fn get_lump_0(&self) -> Option<Vec<HashMap<String, String>>> {
-            let lump_info = self.get_lump_info(0)?;
-            let mut f = File::open(&self.path).ok()?;
-            let lump0_vec = read_exact_from_file(&mut f, lump_info.file_ofs as u64, lump_info.file_len as usize)?;
-            let lump0_str = String::from_utf8(lump0_vec).ok()?;
-            let re_braces = Regex::new(r"\{([^}]*)\}").ok()?;
-            let re_props = Regex::new(r#""([^"]+)"\s*"([^"]+)""#).ok()?;
-            Some(re_braces.captures_iter(&lump0_str).map(|caps| {
-                re_props.captures_iter(&caps[1].to_string()).fold(HashMap::new(), |mut acc, caps| {
-                    acc.insert(caps[1].to_string(), caps[2].to_string());
-                    acc
-                })
-            }).collect())
+        pub fn version(&self) -> i32 {
+            self.version
+        }
+
+        pub fn map_revision(&self) -> i32 {
+            self.map_revision
+        }
+
+        pub fn is_big_endian(&self) -> bool {
+            self.big_endian
+        }
+
+        pub fn has_l4d2_lumps(&self) -> bool {
+            self.l4d2_lumps
+        }
+
+        pub fn lumps(&self) -> &[LumpT] {
+            &self.lumps
+        }
+
+        pub fn map_name(&self) -> String {
+            Path::new(&self.path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+        }
+
+        fn open(&self) -> Result<Box<dyn ReadSeek + '_>> {
+            match &self.bytes {
+                Some(bytes) => Ok(Box::new(Cursor::new(bytes.as_slice()))),
+                None => Ok(Box::new(File::open(&self.path).with_path(Path::new(&self.path))?)),
+            }
+        }
+
+        fn read_all(&self) -> Result<Vec<u8>> {
+            match &self.bytes {
+                Some(bytes) => Ok(bytes.clone()),
+                None => fs::read(&self.path).with_path(Path::new(&self.path)),
+            }
+        }
+
+        pub fn get_lump_info(&self, lump_id: usize) -> Result<&LumpT> {
+            self.lumps.get(lump_id)
+                .ok_or_else(|| Error::malformed("lump index out of range").with_lump(lump_id).with_path(Path::new(&self.path)))
+        }
+
+        pub fn get_lump_0(&self) -> Result<Vec<Entity>> {
+            if let Some(entities) = &self.entities {
+                return Ok(entities.clone());
+            }
+            parse_entities(&self.get_lump_data(ENTITY_LUMP)?).with_lump(ENTITY_LUMP).with_path(Path::new(&self.path))
+        }
+
+        // Edited entities are used for content lookup and written out by every write_* call.
+        pub fn set_entities(&mut self, entities: Vec<Entity>) {
+            self.entities = Some(entities);
+        }
+
+        pub fn import_ent(&mut self, ent_path: &Path) -> Result<()> {
+            let ent_bytes = fs::read(ent_path).with_path(ent_path)?;
+            self.set_entities(parse_entities(&ent_bytes).with_path(ent_path)?);
+            Ok(())
+        }
+
+        pub fn export_ent(&self, ent_path: &Path) -> Result<()> {
+            if let Some(parent_dir) = ent_path.parent() {
+                fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+            }
+            fs::write(ent_path, serialize_entities(&self.get_lump_0()?)?).with_path(ent_path)
         }
 
-        pub fn get_lump_14(&self) -> Option<Vec<DModelT>> {
-            let mut f = File::open(&self.path).ok()?;
-            let lump_info = self.get_lump_info(14)?;
+        pub fn get_lump_14(&self) -> Result<Vec<DModelT>> {
+            let lump_data = self.get_lump_data(14)?;
+            let mut f = Cursor::new(&lump_data);
             let size_vec = vec![VECTOR_SIZE, VECTOR_SIZE, VECTOR_SIZE, I32_SIZE, I32_SIZE, I32_SIZE];
-            Some((0..(lump_info.file_len as usize / DMODEL_SIZE)).filter_map(|i| {
-                let segments = read_segments_from_file(&mut f, (lump_info.file_ofs as usize + DMODEL_SIZE * i) as u64, &size_vec)?;
-                Some(DModelT {
-                    mins: Vector::from_u8_vec(&segments[0])?,
-                    maxs: Vector::from_u8_vec(&segments[1])?,
-                    origin: Vector::from_u8_vec(&segments[2])?,
-                    headnode: i32::from_u8_slice(&segments[3])?,
-                    firstface: i32::from_u8_slice(&segments[4])?,
-                    numfaces: i32::from_u8_slice(&segments[5])?,
+            (0..(lump_data.len() / DMODEL_SIZE)).map(|i| {
+                let ofs = (DMODEL_SIZE * i) as u64;
+                let segments = read_segments_from_file(&mut f, ofs, &size_vec)?;
+                Ok(DModelT {
+                    mins: Vector::from_u8_vec(&segments[0], self.big_endian).with_field("mins")?,
+                    maxs: Vector::from_u8_vec(&segments[1], self.big_endian).with_field("maxs")?,
+                    origin: Vector::from_u8_vec(&segments[2], self.big_endian).with_field("origin")?,
+                    headnode: i32::from_bytes_at_endian(&segments[3], 0, self.big_endian).with_field("headnode")?,
+                    firstface: i32::from_bytes_at_endian(&segments[4], 0, self.big_endian).with_field("firstface")?,
+                    numfaces: i32::from_bytes_at_endian(&segments[5], 0, self.big_endian).with_field("numfaces")?,
                 })
-            }).collect())
+            }).collect::<Result<_>>().with_lump(14).with_path(Path::new(&self.path))
         }
 
-        pub fn get_lump_35(&self) -> Option<HashMap<i32, DGameLumpT>> {
-            let mut f = File::open(&self.path).ok()?;
-            let lump_info = self.get_lump_info(35)?;
+        pub fn get_lump_35(&self) -> Result<HashMap<i32, DGameLumpT>> {
+            let mut f = self.open()?;
+            let lump_info = self.get_lump_info(GAME_LUMP)?;
             let lump_ofs = lump_info.file_ofs;
-            let lump_count = i32::from_u8_slice(&read_exact_from_file(&mut f, lump_ofs as u64, I32_SIZE)?)?;
+            if lump_info.file_len <= 0 {
+                return Ok(HashMap::new());
+            }
+            let lump_count = i32::from_bytes_at_endian(&read_exact_from_file(&mut f, lump_ofs as u64, I32_SIZE)?, 0, self.big_endian)
+                .with_field("lumpCount")
+                .with_lump(GAME_LUMP)
+                .with_path(Path::new(&self.path))?;
             let size_vec = vec![I32_SIZE, U16_SIZE, U16_SIZE, I32_SIZE, I32_SIZE];
-            Some((0..lump_count).filter_map(|i| {
-                let segments = read_segments_from_file(&mut f, (lump_ofs as usize + I32_SIZE + DGAME_LUMP_SIZE * i as usize) as u64, &size_vec)?;
-                let id = i32::from_u8_slice(&segments[0])?;
-                Some((
+            (0..lump_count).map(|i| {
+                let ofs = (lump_ofs as usize + I32_SIZE + DGAME_LUMP_SIZE * i as usize) as u64;
+                let segments = read_segments_from_file(&mut f, ofs, &size_vec).with_field("dgamelump_t")?;
+                let id = i32::from_bytes_at_endian(&segments[0], 0, self.big_endian).with_field("id")?;
+                Ok((
                     id,
                     DGameLumpT {
                         id,
-                        flags: u16::from_u8_slice(&segments[1])?,
-                        version: u16::from_u8_slice(&segments[2])?,
-                        file_ofs: i32::from_u8_slice(&segments[3])?,
-                        file_len: i32::from_u8_slice(&segments[4])?,
+                        flags: u16::from_bytes_at_endian(&segments[1], 0, self.big_endian).with_field("flags")?,
+                        version: u16::from_bytes_at_endian(&segments[2], 0, self.big_endian).with_field("version")?,
+                        file_ofs: i32::from_bytes_at_endian(&segments[3], 0, self.big_endian).with_field("fileofs")?,
+                        file_len: i32::from_bytes_at_endian(&segments[4], 0, self.big_endian).with_field("filelen")?,
                     },
                 ))
-            }).collect())
+            }).collect::<Result<_>>().with_lump(GAME_LUMP).with_path(Path::new(&self.path))
         }
 
-        pub fn get_prop_static(&self) -> Option<Vec<String>> {
-            let mut f = File::open(&self.path).ok()?;
+        // The `sprp` game lump's version and contents, if the map has static props.
+        fn get_static_prop_data(&self) -> Result<Option<(u16, Vec<u8>)>> {
             let prop_static_id = 1936749168;
             let lump35 = self.get_lump_35()?;
-            let prop_static_info = lump35.get(&prop_static_id)?;
-            let ofs = prop_static_info.file_ofs;
-            let dict_entries = i32::from_u8_slice(&read_exact_from_file(&mut f, ofs as u64, I32_SIZE)?)?;
-            Some((0..dict_entries).filter_map(|i| {
-                Some(String::from_utf8(read_exact_from_file(&mut f, (ofs as usize + I32_SIZE + i as usize * PS_NAME_SIZE) as u64, PS_NAME_SIZE)?).ok()?.replace("\0", ""))
-            }).collect())
-        }
-
-        pub fn get_lump_43(&self) -> Option<Vec<String>> {
-            let mut f = File::open(&self.path).ok()?;
-            let lump_info = self.get_lump_info(43)?;
-            let lump43_str = String::from_utf8(read_exact_from_file(&mut f, lump_info.file_ofs as u64, lump_info.file_len as usize)?).ok()?;
-            Some(lump43_str
+            let prop_static_info = match lump35.get(&prop_static_id) {
+                Some(r) => r,
+                None => return Ok(None),
+            };
+            Ok(Some((prop_static_info.version, self.get_game_lump_data(prop_static_info)?)))
+        }
+
+        pub fn get_static_props(&self) -> Result<StaticPropLump> {
+            let (version, game_lump_data) = match self.get_static_prop_data()? {
+                Some(r) => r,
+                None => return Ok(StaticPropLump::default()),
+            };
+            parse_static_props(&game_lump_data, version, self.big_endian)
+                .with_lump(GAME_LUMP)
+                .with_path(Path::new(&self.path))
+        }
+
+        pub fn get_prop_static(&self) -> Result<Vec<String>> {
+            let game_lump_data = match self.get_static_prop_data()? {
+                Some((_, r)) => r,
+                None => return Ok(Vec::new()),
+            };
+            parse_static_prop_names(&game_lump_data, self.big_endian)
+                .with_lump(GAME_LUMP)
+                .with_path(Path::new(&self.path))
+        }
+
+        pub fn get_lump_43(&self) -> Result<Vec<String>> {
+            let lump43_str = String::from_utf8(self.get_lump_data(43)?).with_lump(43).with_path(Path::new(&self.path))?;
+            Ok(lump43_str
                 .split_terminator('\0')
                 .map(|s| format!("{}.vmt", s.to_lowercase()))
                 .collect())
         }
 
-        pub fn download_content(&self, find_path: &Path, output_path: &Path) {
-            let find_mats_path = find_path.join("materials");
-            let find_sound_path = find_path.join("sound");
-            let output_mats_path = output_path.join("materials");
-            let output_sound_path = output_path.join("sound");
-            let mut passed_path_strs: HashSet<&String> = HashSet::new();
+        pub fn get_pakfile(&self) -> Result<ZipArchive> {
+            let lump_data = self.get_lump_data(PAKFILE_LUMP)?;
+            if lump_data.is_empty() {
+                return Ok(ZipArchive::default());
+            }
+            ZipArchive::new(lump_data).with_lump(PAKFILE_LUMP).with_path(Path::new(&self.path))
+        }
 
-            let lump0 = match self.get_lump_0() {
-                Some(r) => r,
-                None => return,
+        // Lump contents as stored in the file, still LZMA-compressed on console and L4D2-era maps.
+        pub fn get_raw_lump_data(&self, lump_id: usize) -> Result<Vec<u8>> {
+            let lump_info = self.get_lump_info(lump_id)?;
+            let mut f = self.open()?;
+            read_exact_from_file(&mut f, lump_info.file_ofs as u64, lump_info.file_len.max(0) as usize)
+                .with_lump(lump_id)
+                .with_path(Path::new(&self.path))
+        }
+
+        pub fn get_lump_data(&self, lump_id: usize) -> Result<Vec<u8>> {
+            let lump_data = self.get_raw_lump_data(lump_id)?;
+            if !is_lzma(&lump_data) {
+                return Ok(lump_data);
+            }
+            decompress_lzma(&lump_data).with_lump(lump_id).with_path(Path::new(&self.path))
+        }
+
+        // A compressed game lump's filelen is its uncompressed size, so the stored size comes from the LZMA header.
+        pub fn get_game_lump_data(&self, game_lump: &DGameLumpT) -> Result<Vec<u8>> {
+            let mut f = self.open()?;
+            let ofs = game_lump.file_ofs as u64;
+            let game_lump_data = if game_lump.flags & GAME_LUMP_COMPRESSED != 0 {
+                let lzma_header = read_exact_from_file(&mut f, ofs, LZMA_HEADER_SIZE).with_field("lzma_header_t");
+                lzma_header
+                    .and_then(|lzma_header| u32::from_bytes_at(&lzma_header, I32_SIZE * 2).with_field("lzmaSize"))
+                    .and_then(|lzma_size| read_exact_from_file(&mut f, ofs, LZMA_HEADER_SIZE + lzma_size as usize))
+                    .and_then(|lzma_data| decompress_lzma(&lzma_data))
+            } else {
+                read_exact_from_file(&mut f, ofs, game_lump.file_len.max(0) as usize)
             };
-            lump0.iter()
-            .fold(Vec::<PathBuf>::new(), |mut acc, ent_info| {
-                let model_val = match ent_info.get("model") {
-                    Some(r) => r,
-                    None => return acc,
-                };
+            game_lump_data.with_field("dgamelump_t").with_lump(GAME_LUMP).with_path(Path::new(&self.path))
+        }
+
+        // Keeps what the map already packs and adds the collected content that is not stock.
+        pub fn build_pakfile(&self, content_set: &ContentSet, file_system: &dyn FileSystem) -> Result<Vec<u8>> {
+            let mut writer = ZipWriter::new();
+            let new_names: HashSet<String> = content_set.to_copy().map(|entry| normalize_name(&entry.path)).collect();
+            let pakfile = self.get_pakfile()?;
+
+            for entry in pakfile.entries() {
+                if !new_names.contains(&normalize_name(&entry.name)) {
+                    writer.add_file(&entry.name, &pakfile.read(entry).with_lump(PAKFILE_LUMP).with_path(Path::new(&self.path))?)?;
+                }
+            }
+            for entry in content_set.to_copy() {
+                writer.add_file(&entry.path, &file_system.read(&entry.path)?)?;
+            }
+
+            writer.finish().with_lump(PAKFILE_LUMP)
+        }
+
+        pub fn write_with_pakfile(&self, output_bsp_path: &Path, content_set: &ContentSet, file_system: &dyn FileSystem) -> Result<()> {
+            let pakfile = self.build_pakfile(content_set, file_system)?;
+            self.write_with_lumps(output_bsp_path, HashMap::from([(PAKFILE_LUMP, pakfile)]))
+        }
+
+        pub fn write(&self, output_bsp_path: &Path) -> Result<()> {
+            self.write_with_lumps(output_bsp_path, HashMap::new())
+        }
+
+        pub fn write_with_lumps(&self, output_bsp_path: &Path, mut new_lumps: HashMap<usize, Vec<u8>>) -> Result<()> {
+            if self.big_endian {
+                return Err(Error::unsupported("writing big-endian console maps").with_path(Path::new(&self.path)));
+            }
+            if let Some(entities) = &self.entities
+                && !new_lumps.contains_key(&ENTITY_LUMP)
+            {
+                let mut entity_lump = serialize_entities(entities).with_lump(ENTITY_LUMP)?;
+                entity_lump.push(0);
+                new_lumps.insert(ENTITY_LUMP, entity_lump);
+            }
+            let bsp_bytes = self.read_all()?;
+            let mut lump_order: Vec<usize> = (0..HEADER_LUMPS).filter(|&i| i != PAKFILE_LUMP).collect();
+            lump_order.sort_by_key(|&i| self.lumps[i].file_ofs);
+            lump_order.push(PAKFILE_LUMP);
+
+            let mut lump_ranges = [(0i32, 0i32); HEADER_LUMPS];
+            let mut four_ccs = self.lumps.each_ref().map(|lump_info| lump_info.four_cc);
+            let mut body = Vec::new();
 
-                let mut add_path_if_unique = |val| {
-                    if !passed_path_strs.contains(val) {
-                        passed_path_strs.insert(val);
-                        acc.push(PathBuf::from(val));
+            for lump_id in lump_order {
+                let lump_info = &self.lumps[lump_id];
+                let mut lump_data = match new_lumps.remove(&lump_id) {
+                    // fourCC holds the uncompressed size of a compressed lump; replacements are written uncompressed.
+                    Some(r) => {
+                        four_ccs[lump_id] = [0; I32_SIZE];
+                        r
                     }
+                    None => get_slice(&bsp_bytes, lump_info.file_ofs as usize, lump_info.file_len.max(0) as usize)
+                        .with_lump(lump_id)
+                        .with_path(Path::new(&self.path))?
+                        .to_vec(),
                 };
-        
-                if let Some(val) = ent_info.get("message") {
-                    add_path_if_unique(val);
+                if lump_data.is_empty() {
+                    continue;
                 }
-                if let Some(val) = ent_info.get("noise1") {
-                    add_path_if_unique(val);
-                }
-                if let Some(val) = ent_info.get("noise2") {
-                    add_path_if_unique(val);
+
+                body.resize(body.len().next_multiple_of(I32_SIZE), 0);
+                let new_ofs = (HEADER_SIZE + body.len()) as i32;
+                if lump_id == GAME_LUMP {
+                    relocate_game_lump(&mut lump_data, new_ofs - lump_info.file_ofs).with_lump(GAME_LUMP).with_path(Path::new(&self.path))?;
                 }
-        
-                let fisrt_symbol = match model_val.get(0..1) {
-                    Some(r) => r,
-                    None => return acc,
+                lump_ranges[lump_id] = (new_ofs, lump_data.len() as i32);
+                body.extend_from_slice(&lump_data);
+            }
+
+            let mut bsp_out = Vec::with_capacity(HEADER_SIZE + body.len());
+            bsp_out.extend_from_slice(&self.ident);
+            bsp_out.extend_from_slice(&self.version.to_le_bytes());
+            for ((lump_info, (file_ofs, file_len)), four_cc) in self.lumps.iter().zip(lump_ranges).zip(four_ccs) {
+                let lump_fields = if self.l4d2_lumps {
+                    [lump_info.version, file_ofs, file_len]
+                } else {
+                    [file_ofs, file_len, lump_info.version]
                 };
-        
-                if fisrt_symbol == "*" {
-                    return acc;
-                }
-        
-                if !passed_path_strs.contains(model_val) {
-                    passed_path_strs.insert(model_val);
-                    acc.push(PathBuf::from(model_val));
+                for field in lump_fields {
+                    bsp_out.extend_from_slice(&field.to_le_bytes());
                 }
-        
-                acc
-            }).iter()
-            .for_each(|file_path| {
-                let ext = match file_path.extension().and_then(|ext| ext.to_str()) {
-                    Some(ext) => ext,
-                    None => return,
+                bsp_out.extend_from_slice(&four_cc);
+            }
+            bsp_out.extend_from_slice(&self.map_revision.to_le_bytes());
+            bsp_out.extend_from_slice(&body);
+
+            if let Some(parent_dir) = output_bsp_path.parent() {
+                fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+            }
+            fs::write(output_bsp_path, bsp_out).with_path(output_bsp_path)
+        }
+
+        pub fn resolve_content(&self, file_system: &dyn FileSystem, skin_mode: SkinMode) -> Result<ContentSet> {
+            let mut content_set = ContentSet::new();
+            let mut context = ResolveContext::new();
+
+            let pakfile = self.get_pakfile()?;
+            let file_system = &PakfileLayer { pakfile: &pakfile, file_system };
+
+            for (index, ent_info) in self.get_lump_0()?.iter().enumerate() {
+                let referrer = Referrer::Entity {
+                    index,
+                    classname: ent_info.classname().into_owned(),
                 };
-            
-                match ext {
-                    "vmt" => {
-                        if let Some(vmt_info) = VMTInfo::new(file_path, &find_mats_path) {
-                            vmt_info.download_with_def_keys(&find_mats_path, &output_mats_path);
+                let skins = [ent_info.get("skin").and_then(|skin| skin.trim().parse::<i32>().ok()).unwrap_or(0)];
+                let skins = (skin_mode == SkinMode::Used).then_some(&skins[..]);
+
+                let file_paths: Vec<String> = ["model", "message", "noise1", "noise2"]
+                    .iter()
+                    .flat_map(|&key| ent_info.get_all(key))
+                    .filter(|val| !val.is_empty() && !val.starts_with('*'))
+                    .map(|val| val.into_owned())
+                    .collect();
+
+                for file_path in file_paths.iter().map(Path::new) {
+                    let ext = match file_path.extension().and_then(|ext| ext.to_str()) {
+                        Some(ext) => ext,
+                        None => continue,
+                    };
+
+                    let result = match ext {
+                        "vmt" => {
+                            resolve_material(file_system, file_path, referrer.clone(), &mut content_set)
                         }
-                    }
-                    "mdl" => {
-                        if let Some(tex_info) = TexturesInfo::new(&find_path.join(file_path)) {
-                            tex_info.download(&find_path, &output_path);
+                        "mdl" => {
+                            resolve_model_skins(file_system, file_path, referrer.clone(), skins, &mut context, &mut content_set)
                         }
-                    }
-                    ext if ["mp3", "wav", "ogg"].contains(&ext) => {
-                        let sound_output_file_path = output_sound_path.join(file_path);
-                        if let Some(parent_dir) = sound_output_file_path.parent() {
-                            let _ = fs::create_dir_all(parent_dir);
-                            let _ = fs::copy(find_sound_path.join(file_path), sound_output_file_path);
+                        "mp3" | "wav" | "ogg" => {
+                            let rel_path_str = Path::new("sound").join(file_path).to_string_lossy().into_owned();
+                            let source = file_system.locate(&rel_path_str);
+                            content_set.insert(&rel_path_str, AssetKind::Sound, referrer.clone(), source);
+                            Ok(())
                         }
+                        _ => Ok(()),
+                    };
+                    if let Err(err) = result {
+                        content_set.add_error(&err);
                     }
-                    _ => {}
                 }
-            });
+            }
 
-            if let Some(prop_static_vec) = self.get_prop_static() {
-                prop_static_vec.iter().for_each(|mdl_path_str| {
-                    let mdl_path = Path::new(mdl_path_str);
-                    let mdl_abs_path = find_path.join(mdl_path);
+            // Props in an unknown layout still name their models; their skins are unknown, so all are kept.
+            let (static_props, skins_known) = match self.get_static_props() {
+                Ok(r) => (r, true),
+                Err(err) => {
+                    content_set.add_error(&err);
+                    (StaticPropLump { names: self.get_prop_static()?, ..StaticPropLump::default() }, false)
+                }
+            };
+            for (index, mdl_path_str) in static_props.names.iter().enumerate() {
+                let mdl_path = Path::new(mdl_path_str);
 
-                    if let Some(tex_info) = TexturesInfo::new(&mdl_abs_path) {
-                        tex_info.download(&find_path, &output_path);
-                    }
-                });
+                let mut skins: Vec<i32> = static_props.props
+                    .iter()
+                    .filter(|prop| prop.prop_type as usize == index)
+                    .map(|prop| prop.skin)
+                    .collect();
+                skins.sort();
+                skins.dedup();
+                if skins.is_empty() {
+                    skins.push(0);
+                }
+                let skins = (skin_mode == SkinMode::Used && skins_known).then_some(skins.as_slice());
+
+                if let Err(err) = resolve_model_skins(file_system, mdl_path, Referrer::StaticProp { index }, skins, &mut context, &mut content_set) {
+                    content_set.add_error(&err);
+                }
             }
 
-            let lump43 = match self.get_lump_43() {
-                Some(r) => r,
-                None => {
-                    return;
-                },
-            };
-            lump43.iter().for_each(|vmt_rel_path_str| {
-                if let Some(vmt_info) = VMTInfo::new(Path::new(vmt_rel_path_str), &find_mats_path) {
-                    vmt_info.download_with_def_keys(&find_mats_path, &output_mats_path);
+            for vmt_rel_path_str in self.get_lump_43()? {
+                if let Err(err) = resolve_material(file_system, Path::new(&vmt_rel_path_str), Referrer::Map, &mut content_set) {
+                    content_set.add_error(&err);
                 }
-            });
+            }
+
+            // Packed files ship inside the map already; they are only extracted with the pakfile itself.
+            content_set.retain(|entry| !pakfile.contains(&entry.path));
+            Ok(content_set)
+        }
+
+        pub fn download_content(&self, file_system: &dyn FileSystem, baseline: &Baseline, writer: &mut dyn ContentWriter, pakfile_mode: PakfileMode, skin_mode: SkinMode) -> Result<ContentSet> {
+            if pakfile_mode == PakfileMode::Extract {
+                let pakfile = self.get_pakfile()?;
+                for entry in pakfile.entries().iter().filter(|entry| !entry.name.ends_with('/')) {
+                    writer.add_file(&entry.name, pakfile.read(entry)?)?;
+                }
+            }
+            let mut content_set = self.resolve_content(file_system, skin_mode)?;
+            content_set.mark_stock(baseline, file_system);
+            content_set.write_to(file_system, writer)?;
+            writer.finish()?;
+            Ok(content_set)
+        }
+
+        // Content clients still have to download: anything the map packs arrives with the map.
+        fn unpacked_content(&self, content_set: &ContentSet) -> Result<ContentSet> {
+            let pakfile = self.get_pakfile()?;
+            let mut unpacked = content_set.clone();
+            unpacked.retain(|entry| !pakfile.contains(&entry.path));
+            Ok(unpacked)
+        }
+
+        // Writes a bzip2 FastDL mirror of the map and the content it does not pack.
+        pub fn write_fastdl(&self, content_set: &ContentSet, file_system: &dyn FileSystem, fastdl_path: &Path) -> Result<()> {
+            let mut writer = FastDlWriter::new(fastdl_path);
+            writer.add_file(&format!("maps/{}.bsp", self.map_name()), self.read_all()?)?;
+            self.unpacked_content(content_set)?.write_to(file_system, &mut writer)?;
+            writer.finish()
+        }
+
+        // Writes the <map>.res download list, which the server reads from beside the map.
+        pub fn write_res_file(&self, content_set: &ContentSet, res_path: &Path) -> Result<()> {
+            let unpacked = self.unpacked_content(content_set)?;
+            let res_text = build_res_file(unpacked.to_copy().map(|entry| entry.path.as_str()));
+            if let Some(parent_dir) = res_path.parent() {
+                fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+            }
+            fs::write(res_path, res_text).with_path(res_path)
+        }
+
+        // Writes lua/autorun/server/<map>_resources.lua under a garrysmod folder or addon root.
+        pub fn write_resource_lua(&self, content_set: &ContentSet, lua_root: &Path, workshop_id: Option<&str>) -> Result<()> {
+            let lua_dir = lua_root.join("lua").join("autorun").join("server");
+            fs::create_dir_all(&lua_dir).with_path(&lua_dir)?;
+            let lua_path = lua_dir.join(format!("{}_resources.lua", self.map_name()));
+            fs::write(&lua_path, build_resource_lua(content_set, workshop_id)).with_path(&lua_path)
         }
     }
 }
+
+pub mod entities;
+pub mod static_props;
@@ -0,0 +1,217 @@
+use simple_utils::utils::{get_slice, FromSlice};
+use simple_utils::error::{Error, Result, ResultExt};
+use crate::reader::Vector;
+
+const PS_NAME_SIZE: usize = 128;
+const MIN_VERSION: u16 = 4;
+const MAX_VERSION: u16 = 13;
+
+// One StaticPropLump_t. Fields a version does not store keep their defaults.
+#[derive(Debug, Clone)]
+pub struct StaticPropLumpT {
+    pub origin: Vector,
+    pub angles: Vector,
+    // Index into the model name dictionary.
+    pub prop_type: u16,
+    pub first_leaf: u16,
+    pub leaf_count: u16,
+    pub solid: u8,
+    pub flags: u8,
+    pub skin: i32,
+    pub fade_min_dist: f32,
+    pub fade_max_dist: f32,
+    pub lighting_origin: Vector,
+    pub forced_fade_scale: f32,
+    pub min_dx_level: u16,
+    pub max_dx_level: u16,
+    pub min_cpu_level: u8,
+    pub max_cpu_level: u8,
+    pub min_gpu_level: u8,
+    pub max_gpu_level: u8,
+    pub diffuse_modulation: [u8; 4],
+    pub disable_x360: bool,
+    pub flags_ex: u32,
+    pub lightmap_resolution_x: u16,
+    pub lightmap_resolution_y: u16,
+    pub uniform_scale: f32,
+}
+
+impl Default for StaticPropLumpT {
+    fn default() -> Self {
+        Self {
+            origin: Vector::default(),
+            angles: Vector::default(),
+            prop_type: 0,
+            first_leaf: 0,
+            leaf_count: 0,
+            solid: 0,
+            flags: 0,
+            skin: 0,
+            fade_min_dist: 0.0,
+            fade_max_dist: 0.0,
+            lighting_origin: Vector::default(),
+            forced_fade_scale: 1.0,
+            min_dx_level: 0,
+            max_dx_level: 0,
+            min_cpu_level: 0,
+            max_cpu_level: 0,
+            min_gpu_level: 0,
+            max_gpu_level: 0,
+            diffuse_modulation: [255; 4],
+            disable_x360: false,
+            flags_ex: 0,
+            lightmap_resolution_x: 0,
+            lightmap_resolution_y: 0,
+            uniform_scale: 1.0,
+        }
+    }
+}
+
+// The whole `sprp` game lump: model names, leaf indices and the props themselves.
+#[derive(Debug, Clone, Default)]
+pub struct StaticPropLump {
+    pub version: u16,
+    pub names: Vec<String>,
+    pub leaves: Vec<u16>,
+    pub props: Vec<StaticPropLumpT>,
+}
+
+impl StaticPropLump {
+    pub fn model_name(&self, prop: &StaticPropLumpT) -> Option<&str> {
+        self.names.get(prop.prop_type as usize).map(String::as_str)
+    }
+}
+
+struct LumpReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl LumpReader<'_> {
+    fn read<T: FromSlice>(&mut self, field: &'static str) -> Result<T> {
+        let value = T::from_bytes_at_endian(self.data, self.pos, self.big_endian).with_field(field)?;
+        self.pos += size_of::<T>();
+        Ok(value)
+    }
+
+    fn read_u8(&mut self, field: &'static str) -> Result<u8> {
+        let value = *get_slice(self.data, self.pos, 1).with_field(field)?.first().unwrap_or(&0);
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn read_vector(&mut self, field: &'static str) -> Result<Vector> {
+        Ok(Vector {
+            x: self.read(field)?,
+            y: self.read(field)?,
+            z: self.read(field)?,
+        })
+    }
+
+    fn read_count(&mut self, field: &'static str) -> Result<usize> {
+        let count: i32 = self.read(field)?;
+        usize::try_from(count).map_err(|_| Error::malformed(format!("negative {}", field)).with_offset(self.pos as u64))
+    }
+}
+
+// Several branches reuse a version number with a different layout, so the per-prop size decides:
+// TF2 and the 2013 SDK write a 72 byte v10 with DX levels and lightmap resolution, CS:GO a 76 byte v10
+// with CPU/GPU levels. Fields appended by v12 and v13 branches follow the uniform scale and are skipped.
+fn read_prop(reader: &mut LumpReader, version: u16, stride: usize) -> Result<StaticPropLumpT> {
+    let start = reader.pos;
+    let sdk2013_v10 = version == 10 && stride == 72;
+    let mut prop = StaticPropLumpT {
+        origin: reader.read_vector("m_Origin")?,
+        angles: reader.read_vector("m_Angles")?,
+        prop_type: reader.read("m_PropType")?,
+        first_leaf: reader.read("m_FirstLeaf")?,
+        leaf_count: reader.read("m_LeafCount")?,
+        solid: reader.read_u8("m_Solid")?,
+        flags: reader.read_u8("m_Flags")?,
+        skin: reader.read("m_Skin")?,
+        fade_min_dist: reader.read("m_FadeMinDist")?,
+        fade_max_dist: reader.read("m_FadeMaxDist")?,
+        lighting_origin: reader.read_vector("m_LightingOrigin")?,
+        ..StaticPropLumpT::default()
+    };
+
+    if version >= 5 {
+        prop.forced_fade_scale = reader.read("m_flForcedFadeScale")?;
+    }
+    if matches!(version, 6 | 7) || sdk2013_v10 {
+        prop.min_dx_level = reader.read("m_nMinDXLevel")?;
+        prop.max_dx_level = reader.read("m_nMaxDXLevel")?;
+    } else if version >= 8 {
+        prop.min_cpu_level = reader.read_u8("m_nMinCPULevel")?;
+        prop.max_cpu_level = reader.read_u8("m_nMaxCPULevel")?;
+        prop.min_gpu_level = reader.read_u8("m_nMinGPULevel")?;
+        prop.max_gpu_level = reader.read_u8("m_nMaxGPULevel")?;
+    }
+    if sdk2013_v10 {
+        prop.flags_ex = reader.read("m_FlagsEx")?;
+        prop.lightmap_resolution_x = reader.read("m_nLightmapResolutionX")?;
+        prop.lightmap_resolution_y = reader.read("m_nLightmapResolutionY")?;
+    } else {
+        if version >= 7 {
+            for channel in prop.diffuse_modulation.iter_mut() {
+                *channel = reader.read_u8("m_DiffuseModulation")?;
+            }
+        }
+        if version >= 9 {
+            // A bool padded to 4 bytes.
+            let disable_x360: u32 = reader.read("m_bDisableX360")?;
+            prop.disable_x360 = disable_x360 & 0xff != 0;
+        }
+        if version >= 10 {
+            prop.flags_ex = reader.read("m_FlagsEx")?;
+        }
+        if version >= 11 {
+            prop.uniform_scale = reader.read("m_flScale")?;
+        }
+    }
+
+    if reader.pos - start > stride {
+        return Err(Error::malformed(format!("static props are {} bytes, too short for version {}", stride, version)).with_offset(start as u64));
+    }
+    reader.pos = start + stride;
+    Ok(prop)
+}
+
+fn read_names(reader: &mut LumpReader) -> Result<Vec<String>> {
+    let dict_entries = reader.read_count("dictEntries")?;
+    (0..dict_entries).map(|_| {
+        let name_bytes = get_slice(reader.data, reader.pos, PS_NAME_SIZE).with_field("StaticPropDictLump_t")?;
+        let name = String::from_utf8(name_bytes.to_vec()).with_offset(reader.pos as u64)?.replace("\0", "");
+        reader.pos += PS_NAME_SIZE;
+        Ok(name)
+    }).collect()
+}
+
+// The model name dictionary leads the lump in every version, so it stays readable when the props do not.
+pub fn parse_static_prop_names(data: &[u8], big_endian: bool) -> Result<Vec<String>> {
+    read_names(&mut LumpReader { data, pos: 0, big_endian })
+}
+
+pub fn parse_static_props(data: &[u8], version: u16, big_endian: bool) -> Result<StaticPropLump> {
+    if !(MIN_VERSION..=MAX_VERSION).contains(&version) {
+        return Err(Error::unsupported(format!("static prop lump version {}", version)));
+    }
+    let mut reader = LumpReader { data, pos: 0, big_endian };
+
+    let names = read_names(&mut reader)?;
+
+    let leaf_entries = reader.read_count("leafEntries")?;
+    let leaves = (0..leaf_entries).map(|_| reader.read("StaticPropLeafLump_t")).collect::<Result<Vec<u16>>>()?;
+
+    let prop_entries = reader.read_count("propEntries")?;
+    if prop_entries == 0 {
+        return Ok(StaticPropLump { version, names, leaves, props: Vec::new() });
+    }
+    let stride = (data.len() - reader.pos) / prop_entries;
+    let props = (0..prop_entries)
+        .map(|_| read_prop(&mut reader, version, stride).with_field("StaticPropLump_t"))
+        .collect::<Result<Vec<StaticPropLumpT>>>()?;
+
+    Ok(StaticPropLump { version, names, leaves, props })
+}
@@ -0,0 +1,109 @@
+// Each test file uses only some of these helpers.
+#![allow(dead_code)]
+
+use std::{fs, path::{Path, PathBuf}};
+use simple_utils::zip::ZipWriter;
+use source_bsp_reader::reader::DHeaderT;
+
+pub const HEADER_SIZE: usize = 4 + 4 + 64 * 16 + 4;
+pub const SPRP_ID: i32 = 1936749168;
+pub const ENTITIES: &str = "{\n\"classname\" \"worldspawn\"\n}\n{\n\"classname\" \"prop_dynamic\"\n\"model\" \"models/props/crate.mdl\"\n}\n";
+
+pub fn static_prop_lump() -> Vec<u8> {
+    let mut sprp = Vec::new();
+    sprp.extend_from_slice(&1i32.to_le_bytes());
+    let mut name = b"models/props/barrel.mdl".to_vec();
+    name.resize(128, 0);
+    sprp.extend_from_slice(&name);
+    sprp.extend_from_slice(&1i32.to_le_bytes());
+    sprp.extend_from_slice(&7u16.to_le_bytes());
+    sprp.extend_from_slice(&2i32.to_le_bytes());
+    // Version 6: the v5 fields plus DX levels, 64 bytes per prop.
+    for skin in [0i32, 3] {
+        for coord in [1.0f32, 2.0, 3.0, 0.0, 90.0, 0.0] {
+            sprp.extend_from_slice(&coord.to_le_bytes());
+        }
+        sprp.extend_from_slice(&[0, 0, 0, 0, 1, 0, 6, 0]);
+        sprp.extend_from_slice(&skin.to_le_bytes());
+        for value in [100.0f32, 200.0, 1.0, 2.0, 3.0, 1.0] {
+            sprp.extend_from_slice(&value.to_le_bytes());
+        }
+        sprp.extend_from_slice(&[0; 4]);
+    }
+    sprp
+}
+
+pub fn pakfile_lump() -> Vec<u8> {
+    let mut writer = ZipWriter::new();
+    writer.add_file("materials/maps/test/packed.vmt", b"\"LightmappedGeneric\" { }").unwrap();
+    writer.finish().unwrap()
+}
+
+// Lumps are laid out entities, texdata strings, game lump, pakfile, each 4 byte aligned.
+pub fn build_bsp(version: i32, l4d2_lumps: bool) -> Vec<u8> {
+    let mut lumps: Vec<(usize, Vec<u8>)> = vec![
+        (0, [ENTITIES.as_bytes(), b"\0"].concat()),
+        (43, b"BRICK/WALL01\0maps/test/packed\0".to_vec()),
+        (35, Vec::new()),
+        (40, pakfile_lump()),
+    ];
+    let mut body = Vec::new();
+    let mut ranges = [(0i32, 0i32); 64];
+    for (lump_id, data) in lumps.iter_mut() {
+        body.resize(body.len().next_multiple_of(4), 0);
+        let ofs = HEADER_SIZE + body.len();
+        if *lump_id == 35 {
+            let sprp = static_prop_lump();
+            data.extend_from_slice(&1i32.to_le_bytes());
+            data.extend_from_slice(&SPRP_ID.to_le_bytes());
+            data.extend_from_slice(&0u16.to_le_bytes());
+            data.extend_from_slice(&6u16.to_le_bytes());
+            data.extend_from_slice(&((ofs + 20) as i32).to_le_bytes());
+            data.extend_from_slice(&(sprp.len() as i32).to_le_bytes());
+            data.extend_from_slice(&sprp);
+        }
+        ranges[*lump_id] = (ofs as i32, data.len() as i32);
+        body.extend_from_slice(data);
+    }
+
+    let mut bsp = b"VBSP".to_vec();
+    bsp.extend_from_slice(&version.to_le_bytes());
+    for (file_ofs, file_len) in ranges {
+        let fields = if l4d2_lumps { [0, file_ofs, file_len] } else { [file_ofs, file_len, 0] };
+        for field in fields {
+            bsp.extend_from_slice(&field.to_le_bytes());
+        }
+        bsp.extend_from_slice(&[0; 4]);
+    }
+    bsp.extend_from_slice(&42i32.to_le_bytes());
+    bsp.extend_from_slice(&body);
+    bsp
+}
+
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("source_bsp_reader_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// The file offset stored in a lump_t of the standard layout.
+pub fn lump_ofs(bsp_bytes: &[u8], lump_id: usize) -> i32 {
+    let ofs = 8 + lump_id * 16;
+    i32::from_le_bytes(bsp_bytes[ofs..ofs + 4].try_into().unwrap())
+}
+
+pub fn reread(bsp_path: &Path) -> DHeaderT {
+    DHeaderT::from_bytes(fs::read(bsp_path).unwrap(), bsp_path).unwrap()
+}
+
+pub fn assert_static_props(dheader_t: &DHeaderT) {
+    let static_props = dheader_t.get_static_props().unwrap();
+    assert_eq!(static_props.version, 6);
+    assert_eq!(static_props.names, ["models/props/barrel.mdl"]);
+    assert_eq!(static_props.leaves, [7]);
+    assert_eq!(static_props.props.iter().map(|prop| prop.skin).collect::<Vec<i32>>(), [0, 3]);
+    assert_eq!(static_props.props[1].angles.y(), 90.0);
+    assert_eq!(static_props.props[1].fade_max_dist, 200.0);
+    assert_eq!(static_props.props[1].max_dx_level, 0);
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use source_bsp_reader::reader::{DHeaderT, Entity, parse_entities, serialize_entities};
+
+mod common;
+use common::{assert_static_props, build_bsp, lump_ofs, reread, temp_dir};
+
+#[test]
+fn parses_quotes_bytes_and_offsets() {
+    let lump = b"{\n\"classname\" \"worldspawn\"\n\"message\" \"say \\\"hi\\\" \xe9t\xe9\"\n}\n{\nclassname light\n\"OnTrigger\" \"a,Kill,,0,-1\"\n\"ontrigger\" \"b,Kill,,1,-1\"\n}\n\0";
+    let entities = parse_entities(lump).unwrap();
+    assert_eq!(entities.len(), 2);
+
+    assert_eq!(entities[0].offset, 0);
+    assert_eq!(entities[0].pairs[1], (b"message".to_vec(), b"say \"hi\" \xe9t\xe9".to_vec()));
+    assert_eq!(entities[0].get("MESSAGE").as_deref(), Some("say \"hi\" \u{fffd}t\u{fffd}"));
+
+    assert_eq!(entities[1].offset, lump.iter().rposition(|&c| c == b'{').unwrap());
+    assert_eq!(entities[1].classname(), "light");
+    assert_eq!(entities[1].get("ONTRIGGER").as_deref(), Some("a,Kill,,0,-1"));
+    assert_eq!(entities[1].get_all("OnTrigger").collect::<Vec<_>>(), ["a,Kill,,0,-1", "b,Kill,,1,-1"]);
+    assert_eq!(entities[1].get("targetname"), None);
+}
+
+#[test]
+fn malformed_lumps_report_the_offset() {
+    for (lump, message) in [
+        (b"{ \"classname\" \"light\" }\n{ \"origin\" \"0 0 0".as_slice(), "unterminated string in entity lump at offset 35"),
+        (b"{ \"classname\" }", "entity key has no value at offset 14"),
+        (b"{ \"classname\" \"light\"", "unterminated entity at offset 0"),
+        (b"\"classname\" \"light\"", "expected `{` in entity lump at offset 0"),
+        (b"{ { }", "unexpected `{` in entity at offset 2"),
+    ] {
+        let err = parse_entities(lump).unwrap_err();
+        assert!(err.to_string().contains(message), "{}", err);
+    }
+}
+
+#[test]
+fn set_replaces_repeats_and_remove_counts() {
+    let mut entity = Entity::new();
+    entity.add("classname", "logic_relay");
+    entity.add("OnTrigger", "a,Kill,,0,-1");
+    entity.add("OnTrigger", "b,Kill,,0,-1");
+    entity.set("ontrigger", "c,Kill,,0,-1");
+    assert_eq!(entity.get_all("OnTrigger").collect::<Vec<_>>(), ["c,Kill,,0,-1"]);
+    entity.set("targetname", "relay");
+    assert_eq!(entity.pairs().last().map(|(k, v)| (k.into_owned(), v.into_owned())), Some(("targetname".to_string(), "relay".to_string())));
+    assert_eq!(entity.remove("ONTRIGGER"), 1);
+    assert_eq!(entity.remove("ontrigger"), 0);
+}
+
+#[test]
+fn edited_entities_move_the_game_lump() {
+    let dir = temp_dir("entities");
+    let bsp_bytes = build_bsp(20, false);
+    let mut dheader_t = DHeaderT::from_bytes(bsp_bytes.clone(), &dir.join("in.bsp")).unwrap();
+    let mut entities = dheader_t.get_lump_0().unwrap();
+    let mut light = Entity::new();
+    light.add("classname", "light");
+    light.add("_light", "255 255 255 200");
+    entities.push(light);
+    entities[1].set("model", "models/props/crate_large.mdl");
+    dheader_t.set_entities(entities.clone());
+
+    let out_path = dir.join("out.bsp");
+    dheader_t.write(&out_path).unwrap();
+    let written = reread(&out_path);
+    assert!(lump_ofs(&fs::read(&out_path).unwrap(), 35) > lump_ofs(&bsp_bytes, 35));
+    assert_eq!(serialize_entities(&written.get_lump_0().unwrap()).unwrap(), serialize_entities(&entities).unwrap());
+    assert_eq!(written.get_lump_0().unwrap()[1].get("model").as_deref(), Some("models/props/crate_large.mdl"));
+    assert_eq!(written.get_lump_43().unwrap(), dheader_t.get_lump_43().unwrap());
+    assert_static_props(&written);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn quotes_cannot_be_serialized() {
+    let mut entities = parse_entities(b"{ \"classname\" \"worldspawn\" }").unwrap();
+    assert_eq!(serialize_entities(&entities).unwrap(), b"{\n\"classname\" \"worldspawn\"\n}\n");
+    entities[0].set("message", "say \"hi\"");
+    let err = serialize_entities(&entities).unwrap_err();
+    assert!(err.to_string().contains("entity key or value contains a quote: say \"hi\""), "{}", err);
+}
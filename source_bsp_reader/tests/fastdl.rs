@@ -0,0 +1,52 @@
+use std::fs;
+use source_bsp_reader::reader::{AssetKind, ContentSet, DHeaderT, Referrer};
+use source_mdl_mats_finder::vfs::MemoryFs;
+
+mod common;
+use common::{build_bsp, temp_dir};
+
+// The map packs materials/maps/test/packed.vmt already; the crate material is loose.
+fn sample_set() -> (ContentSet, MemoryFs) {
+    let mut file_system = MemoryFs::new();
+    file_system.insert("materials/models/props/crate.vmt", b"\"VertexLitGeneric\" { }".to_vec());
+    file_system.insert("sound/ambient/wind.wav", vec![7; 64]);
+    let mut content_set = ContentSet::new();
+    content_set.insert("Materials/Maps/Test/Packed.vmt", AssetKind::Material, Referrer::Map, Some("pakfile".to_string()));
+    content_set.insert("materials/models/props/crate.vmt", AssetKind::Material, Referrer::Map, Some("memory".to_string()));
+    content_set.insert("sound/ambient/wind.wav", AssetKind::Sound, Referrer::Map, Some("memory".to_string()));
+    content_set.insert("materials/models/props/missing.vmt", AssetKind::Material, Referrer::Map, None);
+    (content_set, file_system)
+}
+
+#[test]
+fn res_file_leaves_out_packed_content() {
+    let dir = temp_dir("res");
+    let dheader_t = DHeaderT::from_bytes(build_bsp(20, false), &dir.join("test.bsp")).unwrap();
+    let (content_set, _) = sample_set();
+
+    let res_path = dir.join("maps/test.res");
+    dheader_t.write_res_file(&content_set, &res_path).unwrap();
+    assert_eq!(fs::read_to_string(&res_path).unwrap(), concat!(
+        "\"resources\"\n{\n",
+        "\t\"materials/models/props/crate.vmt\" \"file\"\n",
+        "\t\"sound/ambient/wind.wav\" \"file\"\n",
+        "}\n",
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fastdl_mirrors_the_map_and_unpacked_content() {
+    let dir = temp_dir("fastdl");
+    let dheader_t = DHeaderT::from_bytes(build_bsp(20, false), &dir.join("test.bsp")).unwrap();
+    let (content_set, file_system) = sample_set();
+
+    let fastdl_path = dir.join("fastdl");
+    dheader_t.write_fastdl(&content_set, &file_system, &fastdl_path).unwrap();
+    for rel_path in ["maps/test.bsp.bz2", "materials/models/props/crate.vmt.bz2", "sound/ambient/wind.wav.bz2"] {
+        assert!(fs::read(fastdl_path.join(rel_path)).unwrap().starts_with(b"BZh"), "{}", rel_path);
+    }
+    assert!(!fastdl_path.join("materials/maps").exists());
+    assert!(!fastdl_path.join("materials/models/props/missing.vmt.bz2").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,79 @@
+use source_bsp_reader::reader::DHeaderT;
+
+mod common;
+use common::{ENTITIES, HEADER_SIZE, SPRP_ID, assert_static_props, static_prop_lump, temp_dir};
+
+// Valve's lzma_header_t: id, actualSize, lzmaSize and the 5 property bytes, then the raw stream.
+fn lzma_lump(data: &[u8]) -> Vec<u8> {
+    let mut lzma_rs_out = Vec::new();
+    lzma_rs::lzma_compress(&mut &data[..], &mut lzma_rs_out).unwrap();
+    let (props, stream) = (&lzma_rs_out[..5], &lzma_rs_out[13..]);
+    let mut lump = b"LZMA".to_vec();
+    lump.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    lump.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+    lump.extend_from_slice(props);
+    lump.extend_from_slice(stream);
+    lump
+}
+
+// A map with a compressed entity lump and a compressed static prop game lump.
+fn build_lzma_bsp() -> Vec<u8> {
+    let entities = lzma_lump(&[ENTITIES.as_bytes(), b"\0"].concat());
+    let entities_ofs = HEADER_SIZE;
+    let game_lump_ofs = (entities_ofs + entities.len()).next_multiple_of(4);
+    let sprp = static_prop_lump();
+    let mut game_lump = Vec::new();
+    game_lump.extend_from_slice(&1i32.to_le_bytes());
+    game_lump.extend_from_slice(&SPRP_ID.to_le_bytes());
+    game_lump.extend_from_slice(&1u16.to_le_bytes());
+    game_lump.extend_from_slice(&6u16.to_le_bytes());
+    game_lump.extend_from_slice(&((game_lump_ofs + 20) as i32).to_le_bytes());
+    game_lump.extend_from_slice(&(sprp.len() as i32).to_le_bytes());
+    game_lump.extend_from_slice(&lzma_lump(&sprp));
+
+    let mut bsp = b"VBSP".to_vec();
+    bsp.extend_from_slice(&20i32.to_le_bytes());
+    for lump_id in 0..64 {
+        let (file_ofs, file_len) = match lump_id {
+            0 => (entities_ofs, entities.len()),
+            35 => (game_lump_ofs, game_lump.len()),
+            _ => (0, 0),
+        };
+        for field in [file_ofs as i32, file_len as i32, 0, 0] {
+            bsp.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    bsp.extend_from_slice(&1i32.to_le_bytes());
+    bsp.extend_from_slice(&entities);
+    bsp.resize(game_lump_ofs, 0);
+    bsp.extend_from_slice(&game_lump);
+    bsp
+}
+
+#[test]
+fn reads_compressed_lumps() {
+    let dir = temp_dir("lzma");
+    let dheader_t = DHeaderT::from_bytes(build_lzma_bsp(), &dir.join("lzma.bsp")).unwrap();
+    assert!(dheader_t.get_raw_lump_data(0).unwrap().starts_with(b"LZMA"));
+    assert_eq!(dheader_t.get_lump_data(0).unwrap(), [ENTITIES.as_bytes(), b"\0"].concat());
+    let entities = dheader_t.get_lump_0().unwrap();
+    assert_eq!(entities[1].get("model").as_deref(), Some("models/props/crate.mdl"));
+
+    let game_lumps = dheader_t.get_lump_35().unwrap();
+    assert_eq!(dheader_t.get_game_lump_data(&game_lumps[&SPRP_ID]).unwrap(), static_prop_lump());
+    assert_static_props(&dheader_t);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn truncated_lzma_lump_is_an_error() {
+    let dir = temp_dir("lzma_truncated");
+    let mut bsp = build_lzma_bsp();
+    // Claim a bigger stream than the lump holds.
+    let lzma_size_ofs = HEADER_SIZE + 8;
+    bsp[lzma_size_ofs..lzma_size_ofs + 4].copy_from_slice(&0x10000u32.to_le_bytes());
+    let dheader_t = DHeaderT::from_bytes(bsp, &dir.join("lzma.bsp")).unwrap();
+    let err = dheader_t.get_lump_data(0).unwrap_err();
+    assert!(err.to_string().contains("lzma_data"), "{}", err);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,138 @@
+use source_bsp_reader::static_props::{StaticPropLumpT, parse_static_prop_names, parse_static_props};
+
+// Writes one prop in the given layout with recognisable values in every field it stores.
+fn prop_bytes(version: u16, sdk2013_v10: bool, skin: i32) -> Vec<u8> {
+    let mut prop = Vec::new();
+    for value in [1.0f32, 2.0, 3.0, 0.0, 90.0, 0.0] {
+        prop.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [1u16, 4, 2] {
+        prop.extend_from_slice(&value.to_le_bytes());
+    }
+    prop.extend_from_slice(&[6, 0x40]);
+    prop.extend_from_slice(&skin.to_le_bytes());
+    for value in [100.0f32, 200.0, 1.0, 2.0, 4.0] {
+        prop.extend_from_slice(&value.to_le_bytes());
+    }
+    if version >= 5 {
+        prop.extend_from_slice(&0.5f32.to_le_bytes());
+    }
+    if matches!(version, 6 | 7) || sdk2013_v10 {
+        prop.extend_from_slice(&80u16.to_le_bytes());
+        prop.extend_from_slice(&95u16.to_le_bytes());
+    } else if version >= 8 {
+        prop.extend_from_slice(&[1, 2, 3, 4]);
+    }
+    if sdk2013_v10 {
+        prop.extend_from_slice(&0x10u32.to_le_bytes());
+        prop.extend_from_slice(&32u16.to_le_bytes());
+        prop.extend_from_slice(&64u16.to_le_bytes());
+        return prop;
+    }
+    if version >= 7 {
+        prop.extend_from_slice(&[10, 20, 30, 40]);
+    }
+    if version >= 9 {
+        prop.extend_from_slice(&1u32.to_le_bytes());
+    }
+    if version >= 10 {
+        prop.extend_from_slice(&0x10u32.to_le_bytes());
+    }
+    if version >= 11 {
+        prop.extend_from_slice(&2.0f32.to_le_bytes());
+    }
+    if version >= 12 {
+        // Branch-specific trailing fields the parser skips.
+        prop.extend_from_slice(&[0xee; 8]);
+    }
+    prop
+}
+
+fn sprp_lump(version: u16, sdk2013_v10: bool) -> Vec<u8> {
+    let mut sprp = Vec::new();
+    sprp.extend_from_slice(&2i32.to_le_bytes());
+    for name in ["models/props/barrel.mdl", "models/props/crate.mdl"] {
+        let mut name = name.as_bytes().to_vec();
+        name.resize(128, 0);
+        sprp.extend_from_slice(&name);
+    }
+    sprp.extend_from_slice(&1i32.to_le_bytes());
+    sprp.extend_from_slice(&7u16.to_le_bytes());
+    sprp.extend_from_slice(&2i32.to_le_bytes());
+    for skin in [0, 3] {
+        sprp.extend_from_slice(&prop_bytes(version, sdk2013_v10, skin));
+    }
+    sprp
+}
+
+fn assert_common_fields(prop: &StaticPropLumpT) {
+    assert_eq!((prop.origin.x(), prop.origin.y(), prop.origin.z()), (1.0, 2.0, 3.0));
+    assert_eq!(prop.angles.y(), 90.0);
+    assert_eq!((prop.prop_type, prop.first_leaf, prop.leaf_count), (1, 4, 2));
+    assert_eq!((prop.solid, prop.flags), (6, 0x40));
+    assert_eq!((prop.fade_min_dist, prop.fade_max_dist), (100.0, 200.0));
+    assert_eq!(prop.lighting_origin.z(), 4.0);
+}
+
+#[test]
+fn parses_every_version() {
+    for version in 4..=13 {
+        let lump = parse_static_props(&sprp_lump(version, false), version, false).unwrap();
+        assert_eq!(lump.version, version);
+        assert_eq!(lump.names, ["models/props/barrel.mdl", "models/props/crate.mdl"]);
+        assert_eq!(lump.leaves, [7]);
+        assert_eq!(lump.props.iter().map(|prop| prop.skin).collect::<Vec<i32>>(), [0, 3], "v{}", version);
+        assert_eq!(lump.model_name(&lump.props[1]), Some("models/props/crate.mdl"));
+
+        let prop = &lump.props[1];
+        assert_common_fields(prop);
+        assert_eq!(prop.forced_fade_scale, if version >= 5 { 0.5 } else { 1.0 });
+        assert_eq!(prop.max_dx_level, if matches!(version, 6 | 7) { 95 } else { 0 }, "v{}", version);
+        assert_eq!(prop.max_gpu_level, if version >= 8 { 4 } else { 0 }, "v{}", version);
+        assert_eq!(prop.diffuse_modulation, if version >= 7 { [10, 20, 30, 40] } else { [255; 4] });
+        assert_eq!(prop.disable_x360, version >= 9);
+        assert_eq!(prop.flags_ex, if version >= 10 { 0x10 } else { 0 });
+        assert_eq!(prop.uniform_scale, if version >= 11 { 2.0 } else { 1.0 });
+    }
+}
+
+#[test]
+fn v10_layout_follows_the_prop_size() {
+    let sdk2013 = sprp_lump(10, true);
+    let csgo = sprp_lump(10, false);
+    assert_eq!(csgo.len() - sdk2013.len(), 2 * (76 - 72));
+
+    let prop = &parse_static_props(&sdk2013, 10, false).unwrap().props[1];
+    assert_common_fields(prop);
+    assert_eq!(prop.skin, 3);
+    assert_eq!((prop.min_dx_level, prop.max_dx_level), (80, 95));
+    assert_eq!((prop.lightmap_resolution_x, prop.lightmap_resolution_y), (32, 64));
+    assert_eq!(prop.flags_ex, 0x10);
+    assert_eq!(prop.max_gpu_level, 0);
+    assert_eq!(prop.diffuse_modulation, [255; 4]);
+
+    let prop = &parse_static_props(&csgo, 10, false).unwrap().props[1];
+    assert_common_fields(prop);
+    assert_eq!(prop.skin, 3);
+    assert_eq!((prop.min_cpu_level, prop.max_gpu_level), (1, 4));
+    assert_eq!(prop.diffuse_modulation, [10, 20, 30, 40]);
+    assert!(prop.disable_x360);
+    assert_eq!(prop.flags_ex, 0x10);
+    assert_eq!(prop.max_dx_level, 0);
+}
+
+#[test]
+fn unsupported_versions_keep_the_model_names() {
+    for version in [3, 14] {
+        let sprp = sprp_lump(6, false);
+        let err = parse_static_props(&sprp, version, false).unwrap_err();
+        assert!(err.to_string().contains(&format!("static prop lump version {}", version)), "{}", err);
+        assert_eq!(parse_static_prop_names(&sprp, false).unwrap().len(), 2);
+    }
+}
+
+#[test]
+fn props_too_short_for_the_version_are_an_error() {
+    let err = parse_static_props(&sprp_lump(5, false), 9, false).unwrap_err();
+    assert!(err.to_string().contains("static props are 60 bytes, too short for version 9"), "{}", err);
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use source_bsp_reader::reader::DHeaderT;
+
+mod common;
+use common::{HEADER_SIZE, assert_static_props, build_bsp, temp_dir};
+
+#[test]
+fn unchanged_write_is_identical() {
+    let dir = temp_dir("identical");
+    for (version, l4d2_lumps) in [(20, false), (21, true)] {
+        let bsp_bytes = build_bsp(version, l4d2_lumps);
+        let dheader_t = DHeaderT::from_bytes(bsp_bytes.clone(), &dir.join("in.bsp")).unwrap();
+        assert_eq!(dheader_t.has_l4d2_lumps(), l4d2_lumps);
+        assert_static_props(&dheader_t);
+
+        let out_path = dir.join(format!("out_{}.bsp", version));
+        dheader_t.write(&out_path).unwrap();
+        assert_eq!(fs::read(&out_path).unwrap(), bsp_bytes);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn csgo_v21_keeps_the_standard_lump_layout() {
+    let dir = temp_dir("csgo");
+    let dheader_t = DHeaderT::from_bytes(build_bsp(21, false), &dir.join("in.bsp")).unwrap();
+    assert_eq!(dheader_t.version(), 21);
+    assert!(!dheader_t.has_l4d2_lumps());
+    assert_eq!(dheader_t.get_lump_43().unwrap(), ["brick/wall01.vmt", "maps/test/packed.vmt"]);
+    assert_static_props(&dheader_t);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn big_endian_maps_are_read_but_not_written() {
+    let dir = temp_dir("big_endian");
+    let mut bsp_bytes = build_bsp(19, false);
+    // Console maps store every header field big-endian, which also turns VBSP into PSBV.
+    for field in bsp_bytes[..HEADER_SIZE].chunks_exact_mut(4) {
+        field.reverse();
+    }
+    let dheader_t = DHeaderT::from_bytes(bsp_bytes, &dir.join("in.bsp")).unwrap();
+    assert_eq!(dheader_t.ident(), b"PSBV");
+    assert!(dheader_t.is_big_endian());
+    assert_eq!(dheader_t.version(), 19);
+    assert_eq!(dheader_t.map_revision(), 42);
+    assert_eq!(dheader_t.get_lump_0().unwrap()[1].classname(), "prop_dynamic");
+
+    let err = dheader_t.write(&dir.join("out.bsp")).unwrap_err();
+    assert!(err.to_string().contains("writing big-endian console maps"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn other_idents_are_rejected() {
+    let dir = temp_dir("ident");
+    let mut bsp_bytes = build_bsp(20, false);
+    bsp_bytes[..4].copy_from_slice(b"IBSP");
+    let err = DHeaderT::from_bytes(bsp_bytes, &dir.join("in.bsp")).unwrap_err();
+    assert!(err.to_string().contains("not a VBSP map"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use source_bsp_reader::reader::{AssetKind, ContentSet, DHeaderT, Referrer, ZipArchive, parse_entities};
+use source_mdl_mats_finder::vfs::MemoryFs;
+
+mod common;
+use common::{assert_static_props, build_bsp, reread, temp_dir};
+
+#[test]
+fn packed_content_joins_the_pakfile() {
+    let dir = temp_dir("pakfile");
+    let dheader_t = DHeaderT::from_bytes(build_bsp(21, true), &dir.join("in.bsp")).unwrap();
+
+    let mut file_system = MemoryFs::new();
+    let vmt = b"\"VertexLitGeneric\" { \"$basetexture\" \"models/props/crate\" }".to_vec();
+    let vtf = vec![0x56; 300];
+    file_system.insert("materials/models/props/crate.vmt", vmt.clone());
+    file_system.insert("materials/models/props/crate.vtf", vtf.clone());
+    let mut content_set = ContentSet::new();
+    for (rel_path, kind) in [("materials/models/props/crate.vmt", AssetKind::Material), ("materials/models/props/crate.vtf", AssetKind::Texture)] {
+        content_set.insert(rel_path, kind, Referrer::Map, Some("memory".to_string()));
+    }
+    content_set.insert("materials/models/props/missing.vmt", AssetKind::Material, Referrer::Map, None);
+
+    let out_path = dir.join("out.bsp");
+    dheader_t.write_with_pakfile(&out_path, &content_set, &file_system).unwrap();
+    let written = reread(&out_path);
+    assert!(written.has_l4d2_lumps());
+    assert_eq!(written.map_revision(), 42);
+    assert_eq!(parse_entities(&written.get_lump_data(0).unwrap()).unwrap().len(), 2);
+    assert_static_props(&written);
+
+    let pakfile: ZipArchive = written.get_pakfile().unwrap();
+    let mut names: Vec<&str> = pakfile.entries().iter().map(|entry| entry.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["materials/maps/test/packed.vmt", "materials/models/props/crate.vmt", "materials/models/props/crate.vtf"]);
+    for (rel_path, contents) in [("materials/models/props/crate.vmt", &vmt), ("materials/models/props/crate.vtf", &vtf)] {
+        let entry = pakfile.find(rel_path).unwrap();
+        assert_eq!(entry.crc32, crc32fast::hash(contents));
+        assert_eq!(&pakfile.read(entry).unwrap(), contents);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
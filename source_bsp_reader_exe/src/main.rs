@@ -1,25 +1,278 @@
 use std::env;
 use std::path::Path;
-use source_bsp_reader::reader::DHeaderT;
+use source_bsp_reader::reader::{OutputOptions, Baseline, ContentSet, DHeaderT, GmaArchive, OutputFormat, PakfileMode, SearchPaths, SkinMode, create_writer};
+use source_mdl_mats_finder::report::{CopyPlan, MissingReport, ReportFormat};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let mut positional: Vec<&String> = Vec::new();
+    let mut pakfile_mode = PakfileMode::Skip;
+    let mut skin_mode = SkinMode::Used;
+    let mut pack_path: Option<&String> = None;
+    let mut write_bsp_path: Option<&String> = None;
+    let mut import_ent_path: Option<&String> = None;
+    let mut export_ent_path: Option<&String> = None;
+    let mut fastdl_path: Option<&String> = None;
+    let mut lua_root: Option<&String> = None;
+    let mut workshop_id: Option<&String> = None;
+    let mut report_format: Option<ReportFormat> = None;
+    let mut baseline_paths: Vec<&String> = Vec::new();
+    let mut addon_dirs: Vec<&String> = Vec::new();
+    let mut output_format = OutputFormat::Dir;
+    let mut output_options = OutputOptions { addon_type: "map".to_string(), ..OutputOptions::default() };
+    let mut dry_run = false;
+    let mut args_iter = args.iter().skip(1);
 
-    if args.len() != 4 {
-        eprintln!("Usage: {} <find_path> <output_path> <bsp_file_path>", args[0]);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--extract-pakfile" => pakfile_mode = PakfileMode::Extract,
+            "--all-skins" => skin_mode = SkinMode::All,
+            "--dry-run" => dry_run = true,
+            "--pack" => pack_path = args_iter.next(),
+            "--write-bsp" => write_bsp_path = args_iter.next(),
+            "--import-ent" => import_ent_path = args_iter.next(),
+            "--export-ent" => export_ent_path = args_iter.next(),
+            "--fastdl" => fastdl_path = args_iter.next(),
+            "--lua" => lua_root = args_iter.next(),
+            "--workshop-id" => workshop_id = args_iter.next(),
+            "--baseline" => baseline_paths.extend(args_iter.next()),
+            "--addons" => addon_dirs.extend(args_iter.next()),
+            "--format" => {
+                output_format = match args_iter.next().and_then(|name| OutputFormat::from_name(name)) {
+                    Some(r) => r,
+                    None => {
+                        eprintln!("--format expects `dir`, `gma`, `vpk` or `zip`");
+                        std::process::exit(1);
+                    },
+                };
+            },
+            "--title" => output_options.title = args_iter.next().cloned().unwrap_or_default(),
+            "--description" => output_options.description = args_iter.next().cloned().unwrap_or_default(),
+            "--deflate" => output_options.deflate = true,
+            "--author" => output_options.author = args_iter.next().cloned().unwrap_or_default(),
+            "--tag" => output_options.tags.extend(args_iter.next().cloned()),
+            "--chunk-size-mb" => {
+                output_options.chunk_size = match args_iter.next().and_then(|size| size.parse::<u64>().ok()) {
+                    Some(size_mb) if size_mb > 0 => size_mb * 1024 * 1024,
+                    _ => {
+                        eprintln!("--chunk-size-mb expects a positive number");
+                        std::process::exit(1);
+                    },
+                };
+            },
+            "--preload-size" => {
+                output_options.preload_size = match args_iter.next().and_then(|size| size.parse::<usize>().ok()) {
+                    Some(r) => r,
+                    None => {
+                        eprintln!("--preload-size expects a byte count");
+                        std::process::exit(1);
+                    },
+                };
+            },
+            "--report" => {
+                report_format = args_iter.next().and_then(|name| ReportFormat::from_name(name));
+                if report_format.is_none() {
+                    eprintln!("--report expects `text` or `json`");
+                    std::process::exit(1);
+                }
+            },
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.len() != 3 {
+        eprintln!("Usage: {} [--dry-run] [--extract-pakfile] [--all-skins] [--pack <output_bsp_path>] [--write-bsp <output_bsp_path>] [--import-ent <ent_path>] [--export-ent <ent_path>] [--fastdl <fastdl_dir>] [--lua <garrysmod_dir>] [--workshop-id <id>] [--report <text|json>] [--baseline <vpk|manifest>]... [--addons <gma_dir>]... [--format <dir|gma|vpk|zip>] [--deflate] [--title <addon_title>] [--description <text>] [--author <name>] [--tag <workshop_tag>]... [--chunk-size-mb <mb>] [--preload-size <bytes>] <find_path|gameinfo.txt> <output_path> <bsp_or_gma_path>", args[0]);
+        std::process::exit(1);
+    }
+
+    let find_path = Path::new(positional[0]);
+    let output_path = Path::new(positional[1]);
+    let bsp_file_path = Path::new(positional[2]);
+
+    if pack_path.is_some() && pakfile_mode == PakfileMode::Extract {
+        eprintln!("--pack writes no loose files, so it cannot be combined with --extract-pakfile");
+        std::process::exit(1);
+    }
+    if import_ent_path.is_some() && pack_path.is_none() && write_bsp_path.is_none() {
+        eprintln!("--import-ent needs --pack or --write-bsp to write the edited map");
+        std::process::exit(1);
+    }
+    if output_options.title.is_empty() {
+        output_options.title = bsp_file_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    }
+
+    let is_gma_input = bsp_file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gma"));
+    let input_gma = if is_gma_input {
+        match GmaArchive::open(bsp_file_path) {
+            Ok(r) => Some(r),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            },
+        }
+    } else {
+        None
+    };
+
+    if let Some(gma) = &input_gma {
+        let bsp_paths = DHeaderT::maps_in_gma(gma);
+        if bsp_paths.len() > 1 {
+            eprintln!("{} holds {} maps, using {}", gma.path().display(), bsp_paths.len(), bsp_paths[0]);
+        }
+    }
+    let dheader_result = match &input_gma {
+        Some(gma) => DHeaderT::from_gma(gma),
+        None => DHeaderT::new(bsp_file_path),
+    };
+    let mut dheader_t = match dheader_result {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
+
+    if let Some(export_ent_path) = export_ent_path.filter(|_| !dry_run)
+        && let Err(err) = dheader_t.export_ent(Path::new(export_ent_path))
+    {
+        eprintln!("Failed to export entities: {}", err);
+        std::process::exit(1);
+    }
+    if let Some(import_ent_path) = import_ent_path
+        && let Err(err) = dheader_t.import_ent(Path::new(import_ent_path))
+    {
+        eprintln!("Failed to import entities: {}", err);
         std::process::exit(1);
     }
 
-    let find_path = Path::new(&args[1]);
-    let output_path = Path::new(&args[2]);
-    let bsp_file_path = Path::new(&args[3]);
+    let mut search_paths = match SearchPaths::open(find_path) {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
+    for addon_dir in addon_dirs {
+        if let Err(err) = search_paths.add_gma_dir(Path::new(addon_dir)) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+    if let Some(gma) = input_gma {
+        search_paths.add(gma);
+    }
+    for skipped in search_paths.skipped() {
+        eprintln!("Skipped unreadable archive: {}", skipped);
+    }
+
+    let mut baseline = Baseline::new();
+    for baseline_path in baseline_paths {
+        if let Err(err) = baseline.load(Path::new(baseline_path)) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
 
-    let dheader_t = match DHeaderT::new(bsp_file_path) {
-        Some(r) => r,
-        None => {
-            return;
+    let mut writer = create_writer(output_format, output_path, &output_options);
+    // Packed content goes into the map itself, so nothing is copied beside it.
+    let content_result = if dry_run || pack_path.is_some() {
+        dheader_t.resolve_content(&search_paths, skin_mode).map(|mut content_set| {
+            content_set.mark_stock(&baseline, &search_paths);
+            content_set
+        })
+    } else {
+        dheader_t.download_content(&search_paths, &baseline, writer.as_mut(), pakfile_mode, skin_mode)
+    };
+    let content_set = match content_result {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
         },
     };
 
-    dheader_t.download_content(find_path, output_path);
-}
\ No newline at end of file
+    print_problems(&content_set);
+    for rejected_path in writer.rejected() {
+        eprintln!("Not allowed in the output format, skipped: {}", rejected_path);
+    }
+
+    if dry_run {
+        let mut plan = CopyPlan::new(&content_set, &search_paths);
+        if pakfile_mode == PakfileMode::Extract {
+            match dheader_t.get_pakfile() {
+                Ok(pakfile) => pakfile.entries().iter().for_each(|entry| plan.add(&entry.name, entry.size as u64, "pakfile")),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        for rejected_path in plan.reject(output_format) {
+            eprintln!("Not allowed in the output format, would skip: {}", rejected_path);
+        }
+        // Keep stdout parseable when it carries the JSON report.
+        if report_format == Some(ReportFormat::Json) {
+            eprintln!("{}", plan.to_text());
+        } else {
+            println!("{}", plan.to_text());
+        }
+    }
+
+    if let Some(report_format) = report_format {
+        let report = MissingReport::new(&bsp_file_path.to_string_lossy(), &content_set);
+        println!("{}", report.render(report_format));
+    }
+
+    if let Some(pack_path) = pack_path.filter(|_| !dry_run)
+        && let Err(err) = dheader_t.write_with_pakfile(Path::new(pack_path), &content_set, &search_paths)
+    {
+        eprintln!("Failed to write packed BSP: {}", err);
+        std::process::exit(1);
+    }
+
+    if let Some(write_bsp_path) = write_bsp_path.filter(|_| !dry_run)
+        && let Err(err) = dheader_t.write(Path::new(write_bsp_path))
+    {
+        eprintln!("Failed to write BSP: {}", err);
+        std::process::exit(1);
+    }
+
+    if let Some(lua_root) = lua_root.filter(|_| !dry_run) {
+        let lua_result = dheader_t.write_resource_lua(&content_set, Path::new(lua_root), workshop_id.map(|id| id.as_str()));
+        if let Err(err) = lua_result {
+            eprintln!("Failed to write resource Lua: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(fastdl_path) = fastdl_path.filter(|_| !dry_run) {
+        // Clients should download the packed or edited map when one was written.
+        let written_map_path = pack_path.or(write_bsp_path).map(Path::new);
+        let served_map = match written_map_path {
+            Some(map_path) => DHeaderT::new(map_path),
+            None => Ok(dheader_t),
+        };
+        let fastdl_result = served_map.and_then(|served_map| {
+            served_map.write_fastdl(&content_set, &search_paths, Path::new(fastdl_path))?;
+            // The .res goes beside the map the server loads; a map inside a GMA has no such place,
+            // so it goes into the content folder's maps/ instead.
+            let res_path = match written_map_path {
+                Some(map_path) => map_path.with_extension("res"),
+                None if is_gma_input => output_path.join("maps").join(format!("{}.res", served_map.map_name())),
+                None => bsp_file_path.with_extension("res"),
+            };
+            served_map.write_res_file(&content_set, &res_path)
+        });
+
+        if let Err(err) = fastdl_result {
+            eprintln!("Failed to write FastDL mirror: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_problems(content_set: &ContentSet) {
+    for err in content_set.errors() {
+        eprintln!("{}", err);
+    }
+    for entry in content_set.broken() {
+        eprintln!("Broken {}: {}", entry.path, entry.error.as_deref().unwrap_or_default());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fmt::Write, fs, path::Path, collections::BTreeMap};
+use simple_utils::utils::{clean_path, normalize_name};
+use simple_utils::error::{Error, Result, ResultExt};
+use crate::vfs::FileSystem;
+use crate::vpk::VpkArchive;
+
+// Stock content every client already has. Each path may carry the CRC32 of the stock file,
+// so a locally modified copy with the same name is still packed.
+#[derive(Debug, Default)]
+pub struct Baseline {
+    entries: BTreeMap<String, (String, Option<u32>)>,
+}
+
+impl Baseline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("vpk")) {
+            self.add_vpk(&VpkArchive::open(path)?);
+            return Ok(());
+        }
+        self.add_manifest(path)
+    }
+
+    pub fn add_vpk(&mut self, vpk: &VpkArchive) {
+        for entry in vpk.entries() {
+            self.insert(&entry.path, Some(entry.crc32));
+        }
+    }
+
+    // One `path` or `path<TAB>crc32` per line, with the CRC in hex; `//` starts a comment.
+    pub fn add_manifest(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read(path).with_path(path)?;
+
+        for (line_index, line) in String::from_utf8_lossy(&text).lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.trim_start().starts_with("//") {
+                continue;
+            }
+            let (rel_path, crc32) = match line.split_once('\t') {
+                Some((rel_path, crc_str)) => {
+                    let crc32 = u32::from_str_radix(crc_str.trim(), 16)
+                        .map_err(|_| Error::malformed(format!("bad CRC32 `{}` on line {}", crc_str.trim(), line_index + 1)).with_path(path))?;
+                    (rel_path, Some(crc32))
+                }
+                None => (line, None),
+            };
+            self.insert(rel_path.trim(), crc32);
+        }
+
+        Ok(())
+    }
+
+    pub fn insert(&mut self, rel_path: &str, crc32: Option<u32>) {
+        self.entries.insert(normalize_name(rel_path), (clean_path(rel_path), crc32));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, rel_path: &str) -> bool {
+        self.entries.contains_key(&normalize_name(rel_path))
+    }
+
+    pub fn is_stock(&self, rel_path: &str, file_system: &dyn FileSystem) -> bool {
+        match self.entries.get(&normalize_name(rel_path)) {
+            None => false,
+            Some((_, None)) => true,
+            Some((_, Some(crc32))) => match file_system.read(rel_path) {
+                Ok(data) => crc32fast::hash(&data) == *crc32,
+                Err(_) => true,
+            },
+        }
+    }
+
+    pub fn to_manifest(&self) -> String {
+        let mut text = String::new();
+        for (rel_path, crc32) in self.entries.values() {
+            let _ = match crc32 {
+                Some(crc32) => writeln!(text, "{}\t{:08x}", rel_path, crc32),
+                None => writeln!(text, "{}", rel_path),
+            };
+        }
+        text
+    }
+}
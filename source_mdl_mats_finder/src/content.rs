@@ -0,0 +1,298 @@
+use std::{fmt, path::Path, collections::{HashMap, hash_map::Entry}};
+use simple_utils::utils::{clean_path, normalize_name, safe_rel_path};
+use simple_utils::error::{Error, Result};
+use crate::baseline::Baseline;
+use crate::output::ContentWriter;
+use crate::propdata::PropData;
+use crate::vfs::FileSystem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AssetKind {
+    Model,
+    ModelCompanion,
+    Material,
+    Texture,
+    Sound,
+    // Game scripts an asset depends on, such as the propdata files that define a model's gibs.
+    Script,
+    Other,
+}
+
+impl AssetKind {
+    pub fn from_path(path: &str) -> Self {
+        let ext = Path::new(path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "mdl" => AssetKind::Model,
+            "vvd" | "vtx" | "phy" | "ani" => AssetKind::ModelCompanion,
+            "vmt" => AssetKind::Material,
+            "vtf" => AssetKind::Texture,
+            "wav" | "mp3" | "ogg" => AssetKind::Sound,
+            "txt" if normalize_name(path).starts_with("scripts/") => AssetKind::Script,
+            _ => AssetKind::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AssetKind::Model => "model",
+            AssetKind::ModelCompanion => "model_companion",
+            AssetKind::Material => "material",
+            AssetKind::Texture => "texture",
+            AssetKind::Sound => "sound",
+            AssetKind::Script => "script",
+            AssetKind::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Referrer {
+    Input,
+    Map,
+    Entity { index: usize, classname: String },
+    StaticProp { index: usize },
+    Asset(String),
+}
+
+impl fmt::Display for Referrer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Referrer::Input => write!(f, "input"),
+            Referrer::Map => write!(f, "map texdata"),
+            Referrer::Entity { index, classname } => write!(f, "entity #{} ({})", index, classname),
+            Referrer::StaticProp { index } => write!(f, "static prop #{}", index),
+            Referrer::Asset(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentEntry {
+    pub path: String,
+    pub kind: AssetKind,
+    pub referenced_by: Vec<Referrer>,
+    pub source: Option<String>,
+    pub stock: bool,
+    // Found, but does not belong to the file that referenced it (a model companion with a
+    // checksum from another build of the model), so it is reported and never copied.
+    pub stale: bool,
+    // Found, but it failed to parse, so what it depends on could not be collected. Still copied.
+    pub error: Option<String>,
+}
+
+impl ContentEntry {
+    pub fn is_found(&self) -> bool {
+        self.source.is_some()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ContentSet {
+    entries: Vec<ContentEntry>,
+    index: HashMap<String, usize>,
+    // Failures that belong to no single asset, such as an unreadable map lump.
+    errors: Vec<String>,
+}
+
+impl ContentSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns true the first time a path is seen, so callers know whether to resolve its dependencies.
+    pub fn insert(&mut self, path: &str, kind: AssetKind, referrer: Referrer, source: Option<String>) -> bool {
+        let key = normalize_name(path);
+        if let Some(&i) = self.index.get(&key) {
+            let entry = &mut self.entries[i];
+            if !entry.referenced_by.contains(&referrer) {
+                entry.referenced_by.push(referrer);
+            }
+            return false;
+        }
+
+        self.index.insert(key, self.entries.len());
+        self.entries.push(ContentEntry {
+            path: clean_path(path),
+            kind,
+            referenced_by: vec![referrer],
+            source,
+            stock: false,
+            stale: false,
+            error: None,
+        });
+        true
+    }
+
+    pub fn mark_stale(&mut self, path: &str) {
+        if let Some(&i) = self.index.get(&normalize_name(path)) {
+            self.entries[i].stale = true;
+        }
+    }
+
+    // Records why an asset could not be parsed; failures for paths not in the set become set-wide errors.
+    pub fn mark_broken(&mut self, path: &str, err: &Error) {
+        match self.index.get(&normalize_name(path)) {
+            Some(&i) => self.entries[i].error = Some(err.to_string()),
+            None => self.add_error(err),
+        }
+    }
+
+    pub fn add_error(&mut self, err: &Error) {
+        self.errors.push(err.to_string());
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.index.contains_key(&normalize_name(path))
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ContentEntry> {
+        self.index.get(&normalize_name(path)).map(|&i| &self.entries[i])
+    }
+
+    pub fn entries(&self) -> &[ContentEntry] {
+        &self.entries
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.iter()
+    }
+
+    pub fn found(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.iter().filter(|entry| entry.is_found())
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.iter().filter(|entry| !entry.is_found() && !entry.stock)
+    }
+
+    pub fn stock(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.iter().filter(|entry| entry.stock)
+    }
+
+    pub fn stale(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.iter().filter(|entry| entry.stale)
+    }
+
+    pub fn broken(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.iter().filter(|entry| entry.error.is_some())
+    }
+
+    pub fn to_copy(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.iter().filter(|entry| entry.is_found() && !entry.stock && !entry.stale)
+    }
+
+    pub fn mark_stock(&mut self, baseline: &Baseline, file_system: &dyn FileSystem) -> usize {
+        let mut stock_count = 0;
+        for entry in &mut self.entries {
+            entry.stock = baseline.is_stock(&entry.path, file_system);
+            stock_count += entry.stock as usize;
+        }
+        stock_count
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&ContentEntry) -> bool) {
+        self.entries.retain(f);
+        self.index = self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (normalize_name(&entry.path), i))
+            .collect();
+    }
+
+    pub fn extend(&mut self, other: ContentSet) {
+        for entry in other.entries {
+            for referrer in entry.referenced_by {
+                self.insert(&entry.path, entry.kind, referrer, entry.source.clone());
+            }
+            if let Some(&i) = self.index.get(&normalize_name(&entry.path)) {
+                self.entries[i].stock |= entry.stock;
+                self.entries[i].stale |= entry.stale;
+                if let Some(error) = entry.error {
+                    self.entries[i].error = Some(error);
+                }
+            }
+        }
+        self.errors.extend(other.errors);
+    }
+
+    pub fn write_to(&self, file_system: &dyn FileSystem, writer: &mut dyn ContentWriter) -> Result<()> {
+        for entry in self.to_copy() {
+            writer.add_file(&entry.path, file_system.read(&entry.path)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn copy_to(&self, file_system: &dyn FileSystem, output_path: &Path) -> Result<()> {
+        for entry in self.to_copy() {
+            file_system.copy_file(&entry.path, &output_path.join(safe_rel_path(&entry.path)?))?;
+        }
+        Ok(())
+    }
+}
+
+// What one resolve has already walked, passed alongside the content set it fills.
+#[derive(Debug, Default)]
+pub struct ResolveContext {
+    // Skins each model's materials were collected for, `None` once all of them were.
+    resolved_skins: HashMap<String, Option<Vec<i32>>>,
+    // The propdata scripts, parsed once for every breakable model of a resolve.
+    prop_data: Option<PropData>,
+}
+
+impl ResolveContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Loaded for the first breakable model; a load failure is recorded and leaves no prop types.
+    pub fn prop_data(&mut self, file_system: &dyn FileSystem, content_set: &mut ContentSet) -> &PropData {
+        self.prop_data.get_or_insert_with(|| {
+            PropData::load(file_system).unwrap_or_else(|err| {
+                content_set.add_error(&err);
+                PropData::default()
+            })
+        })
+    }
+
+    // Records a request to resolve `skins` of a model (`None` for every skin) and returns false when
+    // earlier requests already covered it.
+    pub fn claim_skins(&mut self, path: &str, skins: Option<&[i32]>) -> bool {
+        let resolved = match self.resolved_skins.entry(normalize_name(path)) {
+            Entry::Vacant(entry) => {
+                entry.insert(skins.map(<[i32]>::to_vec));
+                return true;
+            }
+            Entry::Occupied(entry) => entry.into_mut(),
+        };
+        let resolved_list = match resolved {
+            Some(r) => r,
+            None => return false,
+        };
+        match skins {
+            Some(skins) => {
+                let new_skins: Vec<i32> = skins.iter().filter(|skin| !resolved_list.contains(skin)).copied().collect();
+                resolved_list.extend(&new_skins);
+                !new_skins.is_empty()
+            }
+            None => {
+                *resolved = None;
+                true
+            }
+        }
+    }
+}
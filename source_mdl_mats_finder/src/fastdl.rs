@@ -0,0 +1,58 @@
+use std::{fs, io::Write, path::{Path, PathBuf}};
+use bzip2::{Compression, write::BzEncoder};
+use simple_utils::utils::{clean_path, safe_rel_path};
+use simple_utils::error::{Result, ResultExt};
+use crate::output::ContentWriter;
+
+pub fn bzip2_compress(contents: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(contents)?;
+    Ok(encoder.finish()?)
+}
+
+// The .res file the server reads to know what clients must download alongside the map.
+pub fn build_res_file<'a>(rel_paths: impl IntoIterator<Item = &'a str>) -> String {
+    let mut text = String::from("\"resources\"\n{\n");
+    for rel_path in rel_paths {
+        text.push_str(&format!("\t\"{}\" \"file\"\n", clean_path(rel_path)));
+    }
+    text.push_str("}\n");
+    text
+}
+
+// Mirrors every file as `<path>.bz2`, the layout sv_downloadurl hosts expect.
+#[derive(Debug)]
+pub struct FastDlWriter {
+    root: PathBuf,
+    files: Vec<String>,
+}
+
+impl FastDlWriter {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+}
+
+impl ContentWriter for FastDlWriter {
+    fn add_file(&mut self, rel_path: &str, contents: Vec<u8>) -> Result<()> {
+        let rel_path = safe_rel_path(rel_path)?;
+        let output_file_path = self.root.join(format!("{}.bz2", rel_path));
+        if let Some(parent_dir) = output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        fs::write(&output_file_path, bzip2_compress(&contents).with_path(&output_file_path)?).with_path(&output_file_path)?;
+        self.files.push(rel_path);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
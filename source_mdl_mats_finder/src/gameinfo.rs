@@ -0,0 +1,265 @@
+use std::{fs, path::{Path, PathBuf}};
+use simple_utils::error::{Error, Result, ResultExt};
+use crate::search::SearchPaths;
+use crate::vfs::LooseDir;
+
+const GAMEINFO_PATH: &str = "|gameinfo_path|";
+const ALL_SOURCE_ENGINE_PATHS: &str = "|all_source_engine_paths|";
+const DIR_PAK_NAME: &str = "pak01_dir.vpk";
+
+#[derive(Debug, Clone)]
+pub enum KvValue {
+    Str(String),
+    Block(Vec<(String, KvValue)>),
+}
+
+impl KvValue {
+    pub fn get(&self, key: &str) -> Option<&KvValue> {
+        match self {
+            KvValue::Block(pairs) => pairs.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v),
+            KvValue::Str(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KvValue::Str(s) => Some(s),
+            KvValue::Block(_) => None,
+        }
+    }
+
+    pub fn pairs(&self) -> &[(String, KvValue)] {
+        match self {
+            KvValue::Block(pairs) => pairs,
+            KvValue::Str(_) => &[],
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '{' | '}' => tokens.push(c.to_string()),
+            '"' => {
+                let mut token = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' if matches!(chars.peek(), Some('"') | Some('\\')) => token.push(chars.next().unwrap_or_default()),
+                        c => token.push(c),
+                    }
+                }
+                tokens.push(token);
+            }
+            // Platform conditionals such as [$WIN32] apply to the previous pair; every platform's
+            // paths are fair game when looking up content, so they are dropped.
+            '[' => {
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '"' | '{' | '}') {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+
+    tokens
+}
+
+fn parse_block(tokens: &mut std::vec::IntoIter<String>) -> Result<Vec<(String, KvValue)>> {
+    let mut pairs = Vec::new();
+
+    while let Some(key) = tokens.next() {
+        if key == "}" {
+            return Ok(pairs);
+        }
+        let value = match tokens.next().as_deref() {
+            Some("{") => KvValue::Block(parse_block(tokens)?),
+            Some(value) => KvValue::Str(value.to_string()),
+            None => return Err(Error::malformed(format!("key `{}` has no value", key))),
+        };
+        pairs.push((key, value));
+    }
+
+    Ok(pairs)
+}
+
+pub fn parse_keyvalues(text: &str) -> Result<KvValue> {
+    let mut tokens = tokenize(text).into_iter();
+    Ok(KvValue::Block(parse_block(&mut tokens)?))
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchPathEntry {
+    pub path_ids: Vec<String>,
+    pub value: String,
+}
+
+impl SearchPathEntry {
+    pub fn has_id(&self, path_id: &str) -> bool {
+        self.path_ids.iter().any(|id| id.eq_ignore_ascii_case(path_id))
+    }
+}
+
+#[derive(Debug)]
+pub struct GameInfo {
+    path: PathBuf,
+    pub game: Option<String>,
+    pub search_paths: Vec<SearchPathEntry>,
+}
+
+impl GameInfo {
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = fs::read(path).with_path(path)?;
+        let root = parse_keyvalues(&String::from_utf8_lossy(&text)).with_path(path)?;
+        let game_info = root.pairs()
+            .first()
+            .map(|(_, value)| value)
+            .ok_or_else(|| Error::malformed("empty gameinfo").with_path(path))?;
+        let search_paths = game_info
+            .get("FileSystem")
+            .and_then(|file_system| file_system.get("SearchPaths"))
+            .ok_or_else(|| Error::malformed("no FileSystem/SearchPaths block").with_field("SearchPaths").with_path(path))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            game: game_info.get("game").and_then(|game| game.as_str()).map(str::to_string),
+            search_paths: search_paths
+                .pairs()
+                .iter()
+                .filter_map(|(key, value)| Some(SearchPathEntry {
+                    path_ids: key.split('+').map(str::to_string).collect(),
+                    value: value.as_str()?.to_string(),
+                }))
+                .collect(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // The mod directory, which is where gameinfo.txt lives.
+    pub fn gameinfo_dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("."))
+    }
+
+    // The install root that holds every mod directory, where the engine binaries live.
+    pub fn base_dir(&self) -> &Path {
+        self.gameinfo_dir().parent().unwrap_or(Path::new("."))
+    }
+
+    pub fn resolve_value(&self, value: &str) -> PathBuf {
+        let value = value.replace('\\', "/");
+        let (base, rel) = if let Some(rel) = value.strip_prefix(GAMEINFO_PATH) {
+            (self.gameinfo_dir(), rel)
+        } else if let Some(rel) = value.strip_prefix(ALL_SOURCE_ENGINE_PATHS) {
+            (self.base_dir(), rel)
+        } else {
+            (self.base_dir(), value.as_str())
+        };
+        let rel = rel.trim_end_matches('/').trim_end_matches("/.");
+        match rel {
+            "" | "." => base.to_path_buf(),
+            rel => base.join(rel),
+        }
+    }
+
+    pub fn mount(&self) -> Result<SearchPaths> {
+        let mut search_paths = SearchPaths::new();
+        let mut mounted: Vec<PathBuf> = Vec::new();
+
+        for entry in self.search_paths.iter().filter(|entry| entry.has_id("game")) {
+            let resolved = self.resolve_value(&entry.value);
+            let resolved_str = resolved.to_string_lossy();
+
+            if let Some(wildcard_dir) = resolved_str.strip_suffix("/*") {
+                let wildcard_dir = Path::new(wildcard_dir);
+                let mut sub_paths: Vec<PathBuf> = match fs::read_dir(wildcard_dir) {
+                    Ok(dir_content) => dir_content.filter_map(|entry| Some(entry.ok()?.path())).collect(),
+                    Err(_) => continue,
+                };
+                sub_paths.sort();
+                for sub_path in sub_paths {
+                    if sub_path.is_dir() {
+                        mount_dir(&mut search_paths, &mut mounted, &sub_path);
+                    } else if is_vpk_dir_file(&sub_path) {
+                        mount_vpk(&mut search_paths, &mut mounted, &sub_path);
+                    } else if sub_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gma")) {
+                        search_paths.add_gma(&sub_path);
+                    }
+                }
+            } else if resolved_str.to_lowercase().ends_with(".vpk") {
+                let stem = &resolved_str[..resolved_str.len() - ".vpk".len()];
+                let dir_vpk_path = if stem.to_lowercase().ends_with("_dir") {
+                    resolved.clone()
+                } else {
+                    PathBuf::from(format!("{}_dir.vpk", stem))
+                };
+                if dir_vpk_path.is_file() {
+                    mount_vpk(&mut search_paths, &mut mounted, &dir_vpk_path);
+                } else if resolved.is_file() {
+                    mount_vpk(&mut search_paths, &mut mounted, &resolved);
+                }
+            } else if resolved.is_dir() {
+                mount_dir(&mut search_paths, &mut mounted, &resolved);
+            }
+        }
+
+        Ok(search_paths)
+    }
+}
+
+fn is_vpk_dir_file(path: &Path) -> bool {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let stem = match file_name.strip_suffix(".vpk") {
+        Some(stem) => stem,
+        None => return false,
+    };
+    // Numbered chunks (pak01_000.vpk) belong to a _dir.vpk and are never mounted on their own.
+    let is_chunk = stem.rsplit_once('_').is_some_and(|(_, idx)| idx.len() == 3 && idx.bytes().all(|b| b.is_ascii_digit()));
+    !is_chunk
+}
+
+fn mount_vpk(search_paths: &mut SearchPaths, mounted: &mut Vec<PathBuf>, vpk_path: &Path) {
+    if mounted.iter().any(|path| path == vpk_path) {
+        return;
+    }
+    search_paths.add_vpk(vpk_path);
+    mounted.push(vpk_path.to_path_buf());
+}
+
+// Like the engine, a directory's pak01_dir.vpk is searched before its loose files.
+fn mount_dir(search_paths: &mut SearchPaths, mounted: &mut Vec<PathBuf>, dir_path: &Path) {
+    if mounted.iter().any(|path| path == dir_path) {
+        return;
+    }
+    let dir_pak_path = dir_path.join(DIR_PAK_NAME);
+    if dir_pak_path.is_file() {
+        mount_vpk(search_paths, mounted, &dir_pak_path);
+    }
+    search_paths.add(LooseDir::new(dir_path));
+    mounted.push(dir_path.to_path_buf());
+}
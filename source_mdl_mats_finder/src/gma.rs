@@ -0,0 +1,300 @@
+use std::{fs::{self, File}, io::{BufRead, BufReader, Read}, path::{Path, PathBuf}, collections::HashMap, time::SystemTime};
+use simple_utils::utils::{clean_path, json_escape, normalize_name, read_exact_from_file, safe_rel_path};
+use simple_utils::error::{Error, Result, ResultExt};
+use crate::output::ContentWriter;
+
+const GMA_IDENT: &[u8; 4] = b"GMAD";
+const GMA_VERSION: u8 = 3;
+
+// Garry's Mod refuses to mount an addon holding any file outside this list; `*` also matches `/`.
+pub const GMA_WHITELIST: [&str; 51] = [
+    "lua/*.lua",
+    "scenes/*.vcd",
+    "particles/*.pcf",
+    "resource/fonts/*.ttf",
+    "scripts/vehicles/*.txt",
+    "resource/localization/*/*.properties",
+    "maps/*.bsp",
+    "maps/*.lmp",
+    "maps/*.nav",
+    "maps/*.ain",
+    "maps/thumb/*.png",
+    "sound/*.wav",
+    "sound/*.mp3",
+    "sound/*.ogg",
+    "materials/*.vmt",
+    "materials/*.vtf",
+    "materials/*.png",
+    "materials/*.jpg",
+    "materials/*.jpeg",
+    "materials/colorcorrection/*.raw",
+    "models/*.mdl",
+    "models/*.vtx",
+    "models/*.phy",
+    "models/*.ani",
+    "models/*.vvd",
+    "gamemodes/*/*.txt",
+    "gamemodes/*/*.fgd",
+    "gamemodes/*/logo.png",
+    "gamemodes/*/icon24.png",
+    "gamemodes/*/gamemode/*.lua",
+    "gamemodes/*/entities/effects/*.lua",
+    "gamemodes/*/entities/weapons/*.lua",
+    "gamemodes/*/entities/entities/*.lua",
+    "gamemodes/*/backgrounds/*.png",
+    "gamemodes/*/backgrounds/*.jpg",
+    "gamemodes/*/backgrounds/*.jpeg",
+    "gamemodes/*/content/models/*.mdl",
+    "gamemodes/*/content/models/*.vtx",
+    "gamemodes/*/content/models/*.phy",
+    "gamemodes/*/content/models/*.ani",
+    "gamemodes/*/content/models/*.vvd",
+    "gamemodes/*/content/materials/*.vmt",
+    "gamemodes/*/content/materials/*.vtf",
+    "gamemodes/*/content/materials/*.png",
+    "gamemodes/*/content/materials/*.jpg",
+    "gamemodes/*/content/materials/*.jpeg",
+    "gamemodes/*/content/scenes/*.vcd",
+    "gamemodes/*/content/particles/*.pcf",
+    "gamemodes/*/content/resource/fonts/*.ttf",
+    "gamemodes/*/content/sound/*.wav",
+    "gamemodes/*/content/maps/*.bsp",
+];
+
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| wildcard_match(rest, &text[i..])),
+        Some((&c, rest)) => text.first() == Some(&c) && wildcard_match(rest, &text[1..]),
+    }
+}
+
+pub fn is_whitelisted(rel_path: &str) -> bool {
+    let rel_path = normalize_name(rel_path);
+    GMA_WHITELIST.iter().any(|pattern| wildcard_match(pattern.as_bytes(), rel_path.as_bytes()))
+}
+
+#[derive(Debug, Clone)]
+pub struct GmaEntry {
+    pub path: String,
+    pub size: u64,
+    pub crc32: u32,
+    offset: u64,
+}
+
+#[derive(Debug)]
+pub struct GmaArchive {
+    path: PathBuf,
+    version: u8,
+    name: String,
+    entries: HashMap<String, GmaEntry>,
+}
+
+fn read_cstr<R: BufRead>(f: &mut R, ofs: &mut u64) -> Result<String> {
+    let start = *ofs;
+    let mut buf = Vec::new();
+    let count_readed = f.read_until(0, &mut buf).with_offset(start)?;
+    if buf.pop() != Some(0) {
+        return Err(Error::malformed("unterminated string in GMA header").with_offset(start));
+    }
+    *ofs += count_readed as u64;
+    String::from_utf8(buf).with_offset(start)
+}
+
+fn read_array<R: Read, const N: usize>(f: &mut R, ofs: &mut u64) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    f.read_exact(&mut buf).with_offset(*ofs)?;
+    *ofs += N as u64;
+    Ok(buf)
+}
+
+impl GmaArchive {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut f = BufReader::new(File::open(path).with_path(path)?);
+        let mut ofs = 0;
+
+        if &read_array::<_, 4>(&mut f, &mut ofs).with_field("ident").with_path(path)? != GMA_IDENT {
+            return Err(Error::malformed("bad GMA ident").with_field("ident").with_path(path));
+        }
+        let [version] = read_array(&mut f, &mut ofs).with_field("version").with_path(path)?;
+        // steamid and timestamp
+        read_array::<_, 16>(&mut f, &mut ofs).with_path(path)?;
+
+        if version > 1 {
+            while !read_cstr(&mut f, &mut ofs).with_field("required_content").with_path(path)?.is_empty() {}
+        }
+        let name = read_cstr(&mut f, &mut ofs).with_field("name").with_path(path)?;
+        read_cstr(&mut f, &mut ofs).with_field("description").with_path(path)?;
+        read_cstr(&mut f, &mut ofs).with_field("author").with_path(path)?;
+        // addon version
+        read_array::<_, 4>(&mut f, &mut ofs).with_path(path)?;
+
+        let mut file_list = Vec::new();
+        loop {
+            let file_number = u32::from_le_bytes(read_array(&mut f, &mut ofs).with_field("file_number").with_path(path)?);
+            if file_number == 0 {
+                break;
+            }
+            let file_path = read_cstr(&mut f, &mut ofs).with_field("file_name").with_path(path)?;
+            let size = i64::from_le_bytes(read_array(&mut f, &mut ofs).with_field("file_size").with_path(path)?);
+            let crc32 = u32::from_le_bytes(read_array(&mut f, &mut ofs).with_field("file_crc").with_path(path)?);
+            let size = u64::try_from(size)
+                .map_err(|_| Error::malformed("negative GMA file size").with_offset(ofs).with_path(path))?;
+            file_list.push((file_path, size, crc32));
+        }
+
+        let mut entries = HashMap::with_capacity(file_list.len());
+        for (file_path, size, crc32) in file_list {
+            entries.insert(normalize_name(&file_path), GmaEntry { path: file_path, size, crc32, offset: ofs });
+            ofs += size;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            version,
+            name,
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &GmaEntry> {
+        self.entries.values()
+    }
+
+    pub fn find(&self, path: &str) -> Option<&GmaEntry> {
+        self.entries.get(&normalize_name(path))
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    pub fn read_entry(&self, entry: &GmaEntry) -> Result<Vec<u8>> {
+        let mut f = File::open(&self.path).with_path(&self.path)?;
+        let data = read_exact_from_file(&mut f, entry.offset, entry.size as usize).with_path(&self.path)?;
+
+        // Older gmad builds wrote a zero CRC for every file.
+        if entry.crc32 != 0 && crc32fast::hash(&data) != entry.crc32 {
+            return Err(Error::malformed(format!("CRC mismatch for {}", entry.path)).with_path(&self.path));
+        }
+
+        Ok(data)
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let entry = self.find(path).ok_or_else(|| Error::not_found(&self.path.join(path)))?;
+        self.read_entry(entry)
+    }
+}
+
+#[derive(Debug)]
+pub struct GmaWriter {
+    output_file_path: PathBuf,
+    pub name: String,
+    pub description: String,
+    pub author: String,
+    pub addon_type: String,
+    pub tags: Vec<String>,
+    files: Vec<(String, Vec<u8>)>,
+    rejected: Vec<String>,
+}
+
+impl GmaWriter {
+    pub fn new(output_file_path: &Path, name: &str) -> Self {
+        Self {
+            output_file_path: output_file_path.to_path_buf(),
+            name: name.to_string(),
+            description: String::new(),
+            author: String::new(),
+            addon_type: "map".to_string(),
+            tags: Vec::new(),
+            files: Vec::new(),
+            rejected: Vec::new(),
+        }
+    }
+
+    // gmad stores the Workshop metadata as a JSON object in the description field.
+    fn description_json(&self) -> String {
+        let tags = self.tags.iter().map(|tag| json_escape(tag)).collect::<Vec<String>>().join(",");
+        format!(
+            "{{\"description\":{},\"type\":{},\"tags\":[{}]}}",
+            json_escape(&self.description),
+            json_escape(&self.addon_type),
+            tags,
+        )
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        let mut out = Vec::new();
+        out.extend_from_slice(GMA_IDENT);
+        out.push(GMA_VERSION);
+        out.extend_from_slice(&0u64.to_le_bytes());
+        out.extend_from_slice(&timestamp.to_le_bytes());
+        // Empty required content list.
+        out.push(0);
+        for header_str in [self.name.clone(), self.description_json(), self.author.clone()] {
+            out.extend_from_slice(header_str.as_bytes());
+            out.push(0);
+        }
+        out.extend_from_slice(&1i32.to_le_bytes());
+
+        for (i, (rel_path, contents)) in self.files.iter().enumerate() {
+            out.extend_from_slice(&(i as u32 + 1).to_le_bytes());
+            out.extend_from_slice(rel_path.as_bytes());
+            out.push(0);
+            out.extend_from_slice(&(contents.len() as i64).to_le_bytes());
+            out.extend_from_slice(&crc32fast::hash(contents).to_le_bytes());
+        }
+        out.extend_from_slice(&0u32.to_le_bytes());
+
+        for (_, contents) in &self.files {
+            out.extend_from_slice(contents);
+        }
+        let crc32 = crc32fast::hash(&out);
+        out.extend_from_slice(&crc32.to_le_bytes());
+        out
+    }
+}
+
+impl ContentWriter for GmaWriter {
+    fn add_file(&mut self, rel_path: &str, contents: Vec<u8>) -> Result<()> {
+        let rel_path = safe_rel_path(rel_path)?.to_lowercase();
+        if !is_whitelisted(&rel_path) {
+            self.rejected.push(clean_path(&rel_path));
+            return Ok(());
+        }
+        match self.files.iter_mut().find(|(path, _)| *path == rel_path) {
+            Some((_, old_contents)) => *old_contents = contents,
+            None => self.files.push((rel_path, contents)),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(parent_dir) = self.output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        fs::write(&self.output_file_path, self.to_bytes()).with_path(&self.output_file_path)
+    }
+
+    fn rejected(&self) -> &[String] {
+        &self.rejected
+    }
+}
@@ -1,12 +1,18 @@
 pub mod finder {
     use std::path::{Path, PathBuf};
-    use std::{fs, vec};
-    use std::fs::File;
-    use std::io::{Seek, Read, SeekFrom};
+    use std::fs;
+    use std::io::{Cursor, Seek, Read, SeekFrom};
     use std::mem::size_of;
     use std::collections::HashMap;
     use regex::Regex;
-    use simple_utils::utils::{FromSlice, read_exact_from_file, read_segments_from_file, null_term_str };
+    use simple_utils::utils::{FromSlice, read_exact_from_file, read_segments_from_file, null_term_str, clean_path };
+    use simple_utils::error::{Error, ErrorKind, Result, ResultExt};
+    use crate::baseline::Baseline;
+    use crate::content::{AssetKind, ContentSet, Referrer, ResolveContext};
+    use crate::gameinfo::{KvValue, parse_keyvalues};
+    use crate::output::ContentWriter;
+    use crate::propdata::resolve_prop_data;
+    use crate::vfs::FileSystem;
 
     const VECTOR_SIZE: usize = 12;
     const TEX_SIZE: usize = 64;
@@ -14,9 +20,37 @@ pub mod finder {
     const I32_SIZE: usize = size_of::<i32>();
     const U8_SIZE: usize = size_of::<u8>();
     const U16_SIZE: usize = size_of::<u16>();
-    fn parse_vmt(vmt_str: &str) -> Option<HashMap<String, String>> {
-        let re = Regex::new(r#""([^"]+)"\s*"([^"]+)""#).ok()?;
-        Some(
+
+    pub const DEFAULT_VMT_KEYS: [&str; 5] = ["$basetexture", "$detail", "$bumpmap", "$envmapmask", "$selfillummask"];
+    pub const MODEL_COMPANION_SUFFIXES: [&str; 8] = [".vvd", ".phy", ".ani", ".dx80.vtx", ".dx90.vtx", ".sw.vtx", ".xbox.vtx", ".vtx"];
+    const VVD_SUFFIX: &str = ".vvd";
+    const VTX_SUFFIX: &str = ".vtx";
+    const DEFAULT_VTX_SUFFIX: &str = ".dx90.vtx";
+    const COMPANION_HEADER_SIZE: usize = 20;
+    const OFS_TO_KEYVALUES: usize = 312;
+    const OFS_TO_INCLUDE_MODELS: usize = 336;
+    const MODEL_GROUP_SIZE: usize = I32_SIZE * 2;
+
+    // Every companion repeats the checksum studiomdl wrote into the .mdl header.
+    fn companion_checksum_ofs(file_name: &str) -> usize {
+        if file_name.ends_with(".vtx") {
+            I32_SIZE * 4
+        } else if file_name.ends_with(".phy") {
+            I32_SIZE * 3
+        } else {
+            I32_SIZE * 2
+        }
+    }
+
+    fn read_companion_checksum(file_system: &dyn FileSystem, rel_path: &str) -> Result<i32> {
+        let mut header = [0u8; COMPANION_HEADER_SIZE];
+        file_system.open(rel_path)?.read_exact(&mut header).with_path(Path::new(rel_path))?;
+        i32::from_bytes_at(&header, companion_checksum_ofs(&rel_path.to_lowercase())).with_field("checksum").with_path(Path::new(rel_path))
+    }
+
+    fn parse_vmt(vmt_str: &str) -> Result<HashMap<String, String>> {
+        let re = Regex::new(r#""([^"]+)"\s*"([^"]+)""#).map_err(|err| Error::malformed(err.to_string()))?;
+        Ok(
             re
                 .captures_iter(vmt_str)
                 .fold(HashMap::new(), |mut acc, caps| {
@@ -32,16 +66,37 @@ pub mod finder {
     }
 
     impl Texture {
-        pub fn new(f: &mut File, i: i32, tex_ofs: i32) -> Option<Self> {
-            let ofs = (tex_ofs + TEX_SIZE as i32 * i) as u64;
-            let tex_buf = read_exact_from_file(f, ofs, TEX_SIZE)?;
-            let name_ofs = i32::from_u8_slice(tex_buf.get(0..I32_SIZE)?)?;
-            Some(Self {
-                name: null_term_str(f, ofs + name_ofs as u64)?,
+        // `ofs` is where the mstudiotexture_t starts; its name offset is relative to it.
+        pub fn new<R: Read + Seek>(f: &mut R, ofs: u64) -> Result<Self> {
+            let tex_buf = read_exact_from_file(f, ofs, TEX_SIZE).with_field("mstudiotexture_t")?;
+            let name_ofs = i32::from_bytes_at(&tex_buf, 0).with_offset(ofs).with_field("sznameindex")?;
+            Ok(Self {
+                name: null_term_str(f, relative_offset(ofs, name_ofs)?).with_field("texture name")?,
             })
         }
     }
 
+    // Offsets of `count` records of `stride` bytes starting at `ofs`. Header counts and offsets are
+    // untrusted, so the whole array has to fit in the file before anything is read or allocated.
+    fn record_offsets(ofs: i32, count: i32, stride: usize, file_len: u64) -> Result<impl Iterator<Item = u64>> {
+        let count = count.max(0) as u64;
+        let stride = stride as u64;
+        let start = u64::try_from(ofs).ok().filter(|_| count > 0).unwrap_or(0);
+        let fits = count
+            .checked_mul(stride)
+            .and_then(|size| size.checked_add(start))
+            .is_some_and(|end| end <= file_len);
+        if !fits {
+            return Err(Error::malformed(format!("{} records of {} bytes at {} run past the end of the file", count, stride, ofs)));
+        }
+        Ok((0..count).map(move |i| start + stride * i))
+    }
+
+    fn relative_offset(base: u64, rel_ofs: i32) -> Result<u64> {
+        base.checked_add_signed(rel_ofs as i64)
+            .ok_or_else(|| Error::malformed(format!("offset {} is out of range", rel_ofs)).with_offset(base))
+    }
+
     #[derive(Debug)]
     pub struct VMTInfo {
         path: String,
@@ -50,251 +105,379 @@ pub mod finder {
     }
 
     impl VMTInfo {
-        pub fn new(vmt_tex_rel_path: &Path, find_mats_path: &Path) -> Option<Self> {
-            let vmt_abs_path = &find_mats_path.join(vmt_tex_rel_path);
+        pub fn new(vmt_tex_rel_path: &Path, file_system: &dyn FileSystem) -> Result<Self> {
+            let rel_path = clean_path(&path_to_string(vmt_tex_rel_path)?);
+            let vmt_path_str = format!("materials/{}", rel_path);
+            let vmt_path = Path::new(&vmt_path_str);
 
-            if !vmt_abs_path.exists() || vmt_abs_path.extension()? != "vmt" {
-                return None;
+            if vmt_path.extension().is_none_or(|ext| ext != "vmt") {
+                return Err(Error::unsupported("not a .vmt file").with_path(vmt_path));
             }
 
-            let mut f = File::open(vmt_abs_path).ok()?;
-            let mut str_buf = String::from("");
-            let _ = f.read_to_string(&mut str_buf);
+            // Comments in VMTs are often saved in a local codepage; keys and values are ASCII either way.
+            let str_buf = String::from_utf8_lossy(&file_system.read(&vmt_path_str)?).into_owned();
 
-            Some(Self {
-                path: String::from(vmt_abs_path.to_str()?),
-                rel_path: String::from(vmt_tex_rel_path.to_str()?),
-                vmt_data: parse_vmt(str_buf.trim())?,
+            Ok(Self {
+                vmt_data: parse_vmt(str_buf.trim()).with_path(vmt_path)?,
+                path: vmt_path_str,
+                rel_path,
             })
         }
         
-        pub fn download_vmt(&self, output_mats_path: &Path) {
-            let vmt_path_str = &self.path;
-            let vmt_rel_path_str = &self.rel_path;
-            let vmt_path = Path::new(vmt_path_str);
-            let vmt_rel_path = Path::new(vmt_rel_path_str);
-            let vmt_tex_new_file_path = output_mats_path.join(vmt_rel_path);
-            let vmt_tex_new_dir_path = match vmt_tex_new_file_path.parent() {
-                Some(r) => r,
-                None => {
-                    return;
-                },
-            };
-
-            let _ = fs::create_dir_all(vmt_tex_new_dir_path);
-            let _ = fs::copy(vmt_path, vmt_tex_new_file_path);
+        pub fn path(&self) -> &str {
+            &self.path
         }
 
-        pub fn download_vtf(&self, find_mats_path: &Path, output_mats_path: &Path, keys: &Vec<&str>) {
-            let vmt_data_ptr = &self.vmt_data;
-            let empty_str_ptr = &String::from("");
-
-            keys.iter().for_each(|&vmt_key| {
-                let vtf_val = vmt_data_ptr.get(vmt_key).unwrap_or(empty_str_ptr);
-
-                if vtf_val.is_empty() {
-                    return;
-                }
-
-                let vtf_file_path_str = format!("{}.vtf", vtf_val);
-                let vtf_file_path = Path::new(&vtf_file_path_str);
-                let vtf_input_file_path = find_mats_path.join(vtf_file_path);
+        pub fn rel_path(&self) -> &str {
+            &self.rel_path
+        }
 
-                if !vtf_input_file_path.exists() {
-                    return;
-                }
+        pub fn resolve(&self, file_system: &dyn FileSystem, keys: &[&str], content_set: &mut ContentSet) -> Result<()> {
+            let referrer = Referrer::Asset(self.path.clone());
 
-                let vtf_output_file_path = output_mats_path.join(vtf_file_path);
-                let vtf_output_parent_dir_path = match vtf_output_file_path.parent() {
-                    Some(r) => r,
-                    None => {
-                        eprintln!("Не удалось получить parent output директории VTF файла: {}", vtf_file_path_str);
-                        return;
-                    }
+            for &vmt_key in keys {
+                let vtf_val = match self.vmt_data.get(vmt_key) {
+                    Some(r) if !r.is_empty() => r,
+                    _ => continue,
                 };
 
-                let _ = fs::create_dir_all(vtf_output_parent_dir_path);
-                let _ = fs::copy(vtf_input_file_path, vtf_output_file_path);
-            });
+                let vtf_file_path_str = format!("materials/{}.vtf", clean_path(vtf_val));
+                let source = file_system.locate(&vtf_file_path_str);
+                content_set.insert(&vtf_file_path_str, AssetKind::Texture, referrer.clone(), source);
+            }
 
-            if let Some(include_path_str) = vmt_data_ptr.get("include") {
+            if let Some(include_path_str) = self.vmt_data.get("include") {
                 let include_path: PathBuf = Path::new(&include_path_str).iter().skip(1).collect();
-                if let Some(vmt_info) = Self::new(&include_path, find_mats_path) {
-                    vmt_info.download_with_def_keys(find_mats_path, output_mats_path);
+                if let Err(err) = resolve_material(file_system, &include_path, referrer, content_set) {
+                    content_set.add_error(&err);
                 }
             }
-        }
 
-        pub fn download(&self, find_mats_path: &Path, output_mats_path: &Path, keys: &Vec<&str>) {
-            self.download_vmt(output_mats_path);
-            self.download_vtf(find_mats_path, output_mats_path, keys);
+            Ok(())
         }
 
-        pub fn download_with_def_keys(&self, find_mats_path: &Path, output_mats_path: &Path) {
-            let vmt_keys = vec!["$basetexture", "$detail", "$bumpmap", "$envmapmask", "$selfillummask"];
-            self.download(find_mats_path, output_mats_path, &vmt_keys);
+        pub fn resolve_with_def_keys(&self, file_system: &dyn FileSystem, content_set: &mut ContentSet) -> Result<()> {
+            self.resolve(file_system, &DEFAULT_VMT_KEYS, content_set)
         }
     }
 
     #[derive(Debug)]
     pub struct TexturesInfo {
         name: String,
+        checksum: i32,
         dirs: Vec<String>,
         textures: Vec<String>,
+        // Zero for animation-only models, which ship without vertex and mesh files.
+        bodypart_count: i32,
+        // One row per skin family, each mapping a skin reference to an index into `textures`.
+        skin_families: Vec<Vec<i16>>,
+        // Models pulled in with $includemodel, usually shared animation MDLs.
+        include_models: Vec<String>,
+        // The $keyvalues text block, which holds prop_data and physgun_interactions.
+        keyvalues: Option<KvValue>,
+        // Why the keyvalues were dropped; only gibs come from them, so the model is still usable.
+        keyvalue_error: Option<Error>,
     }
 
     impl TexturesInfo {
-        pub fn new(path: &Path) -> Option<Self> {
-            let mut f = match File::open(path) {
-                Ok(r) => r,
-                Err(err) => {
-                    eprintln!("1: {} {}", err, path.to_str().unwrap());
-                    return None;
-                }
-            };
+        pub fn new(path: &Path) -> Result<Self> {
+            Self::from_bytes(fs::read(path).with_path(path)?, path)
+        }
+
+        pub fn from_bytes(mdl_bytes: Vec<u8>, path: &Path) -> Result<Self> {
+            let file_len = mdl_bytes.len() as u64;
+            let mut f = Cursor::new(mdl_bytes);
             let mut_ptr = &mut f;
-            let size_vec = vec![I32_SIZE, I32_SIZE, I32_SIZE, I32_SIZE];
-            let tex_info_segments = read_segments_from_file(mut_ptr, OFS_TO_TEX as u64, &size_vec)?;
-            let tex_count = i32::from_u8_slice(&tex_info_segments[0])?;
-            let tex_ofs = i32::from_u8_slice(&tex_info_segments[1])?;
-            let texdir_count   = i32::from_u8_slice(&tex_info_segments[2])?;
-            let texdir_ofs  = i32::from_u8_slice(&tex_info_segments[3])?;
-            let dirs = (0..texdir_count).filter_map(|i| {
-                mut_ptr.seek(SeekFrom::Start((texdir_ofs + (2 * U16_SIZE as i32) * i) as u64)).ok()?;
+            let size_vec = vec![I32_SIZE; 8];
+            let tex_info_segments = read_segments_from_file(mut_ptr, OFS_TO_TEX as u64, &size_vec).with_path(path)?;
+            let tex_count = i32::from_u8_slice(&tex_info_segments[0]).with_field("numtextures").with_path(path)?;
+            let tex_ofs = i32::from_u8_slice(&tex_info_segments[1]).with_field("textureindex").with_path(path)?;
+            let texdir_count = i32::from_u8_slice(&tex_info_segments[2]).with_field("numcdtextures").with_path(path)?;
+            let texdir_ofs = i32::from_u8_slice(&tex_info_segments[3]).with_field("cdtextureindex").with_path(path)?;
+            let skinref_count = i32::from_u8_slice(&tex_info_segments[4]).with_field("numskinref").with_path(path)?;
+            let skinfamily_count = i32::from_u8_slice(&tex_info_segments[5]).with_field("numskinfamilies").with_path(path)?;
+            let skin_ofs = i32::from_u8_slice(&tex_info_segments[6]).with_field("skinindex").with_path(path)?;
+            let bodypart_count = i32::from_u8_slice(&tex_info_segments[7]).with_field("numbodyparts").with_path(path)?;
+            let dirs = record_offsets(texdir_ofs, texdir_count, I32_SIZE, file_len).with_field("cdtextures").with_path(path)?.map(|ofs| {
+                mut_ptr.seek(SeekFrom::Start(ofs)).with_offset(ofs)?;
                 let mut u16_bytes: [u8; 2] = [0; 2];
-                let _ = mut_ptr.read_exact(&mut u16_bytes).ok()?;
+                mut_ptr.read_exact(&mut u16_bytes).with_offset(ofs).with_field("cdtexture offset")?;
                 let new_ofs = u16::from_le_bytes(u16_bytes);
-                null_term_str(mut_ptr, new_ofs as u64)
-            }).collect();
-            let textures = (0..tex_count).filter_map(|i| {
-                Some(Texture::new(mut_ptr, i, tex_ofs)?.name)
-            }).collect();
-            let name_u8_vec = read_exact_from_file(mut_ptr, (I32_SIZE * 3) as u64, 64)?
+                null_term_str(mut_ptr, new_ofs as u64).with_field("cdtexture")
+            }).collect::<Result<Vec<String>>>().with_path(path)?;
+            let textures = record_offsets(tex_ofs, tex_count, TEX_SIZE, file_len).with_field("textures").with_path(path)?.map(|ofs| {
+                Ok(Texture::new(mut_ptr, ofs)?.name)
+            }).collect::<Result<Vec<String>>>().with_path(path)?;
+            let family_size = U16_SIZE * skinref_count.max(0) as usize;
+            let skin_families = record_offsets(skin_ofs, skinfamily_count, family_size, file_len).with_field("skin families").with_path(path)?.map(|ofs| {
+                let family_bytes = read_exact_from_file(mut_ptr, ofs, family_size).with_field("skin family")?;
+                family_bytes.chunks_exact(U16_SIZE).map(i16::from_u8_slice).collect::<Result<Vec<i16>>>().with_offset(ofs)
+            }).collect::<Result<Vec<Vec<i16>>>>().with_path(path)?;
+            let name_u8_vec = read_exact_from_file(mut_ptr, (I32_SIZE * 3) as u64, 64).with_field("name").with_path(path)?
                 .into_iter()
                 .filter(|&el| el != 0)
                 .collect();
-            Some(Self {
-                name: String::from_utf8(name_u8_vec).ok()?,
+            let include_segments = read_segments_from_file(mut_ptr, OFS_TO_INCLUDE_MODELS as u64, &[I32_SIZE, I32_SIZE]).with_path(path)?;
+            let include_count = i32::from_u8_slice(&include_segments[0]).with_field("numincludemodels").with_path(path)?;
+            let include_ofs = i32::from_u8_slice(&include_segments[1]).with_field("includemodelindex").with_path(path)?;
+            let include_models = record_offsets(include_ofs, include_count, MODEL_GROUP_SIZE, file_len).with_field("include models").with_path(path)?.map(|ofs| {
+                // mstudiomodelgroup_t: szlabelindex, sznameindex, both relative to the entry.
+                let name_ofs = i32::from_u8_slice(&read_exact_from_file(mut_ptr, ofs + I32_SIZE as u64, I32_SIZE)?).with_field("sznameindex")?;
+                null_term_str(mut_ptr, relative_offset(ofs, name_ofs)?).with_field("include model")
+            }).collect::<Result<Vec<String>>>().with_path(path)?;
+            let keyvalue_segments = read_segments_from_file(mut_ptr, OFS_TO_KEYVALUES as u64, &[I32_SIZE, I32_SIZE]).with_path(path)?;
+            let keyvalue_ofs = i32::from_u8_slice(&keyvalue_segments[0]).with_field("keyvalueindex").with_path(path)?;
+            let keyvalue_size = i32::from_u8_slice(&keyvalue_segments[1]).with_field("keyvaluesize").with_path(path)?;
+            let keyvalue_result = if keyvalue_ofs > 0 && keyvalue_size > 0 {
+                record_offsets(keyvalue_ofs, 1, keyvalue_size as usize, file_len)
+                    .and_then(|_| read_exact_from_file(mut_ptr, keyvalue_ofs as u64, keyvalue_size as usize))
+                    .and_then(|keyvalue_bytes| parse_keyvalues(String::from_utf8_lossy(&keyvalue_bytes).trim_end_matches('\0')))
+                    .with_field("keyvalues")
+                    .with_path(path)
+                    .map(Some)
+            } else {
+                Ok(None)
+            };
+            let (keyvalues, keyvalue_error) = match keyvalue_result {
+                Ok(r) => (r, None),
+                Err(err) => (None, Some(err)),
+            };
+            let checksum = i32::from_u8_slice(&read_exact_from_file(mut_ptr, (I32_SIZE * 2) as u64, I32_SIZE)?)
+                .with_field("checksum")
+                .with_path(path)?;
+            Ok(Self {
+                name: String::from_utf8(name_u8_vec).with_field("name").with_path(path)?,
+                checksum,
                 dirs, 
-                textures 
+                textures,
+                bodypart_count,
+                skin_families,
+                include_models,
+                keyvalues,
+                keyvalue_error,
             })
         }
-        
-        pub fn download(&self, find_path: &Path, output_path: &Path) {
-            let find_mdl_path = find_path.join("models");
-            let find_mats_path = find_path.join("materials");
-
-            let output_mdls_path = output_path.join("models");
-            let output_mats_path = output_path.join("materials");
-
-            let self_name_path = Path::new(&self.name);
-            let self_name_parent = match self_name_path.parent() {
-                Some(parent) => parent,
-                None => {
-                    eprintln!("Не удалось получить родительскую директорию!");
-                    return;
-                }
+
+        pub fn checksum(&self) -> i32 {
+            self.checksum
+        }
+
+        // studiomdl wraps the block in `mdlkeyvalue`, older compilers wrote the pairs directly.
+        pub fn keyvalues(&self) -> Option<&KvValue> {
+            let keyvalues = self.keyvalues.as_ref()?;
+            Some(keyvalues.get("mdlkeyvalue").filter(|block| matches!(block, KvValue::Block(_))).unwrap_or(keyvalues))
+        }
+
+        pub fn include_models(&self) -> &[String] {
+            &self.include_models
+        }
+
+        pub fn textures(&self) -> &[String] {
+            &self.textures
+        }
+
+        pub fn skin_families(&self) -> &[Vec<i16>] {
+            &self.skin_families
+        }
+
+        // Texture names the given skins draw with; `None` keeps every texture. Like the engine,
+        // an out of range skin falls back to the default skin.
+        pub fn skin_textures(&self, skins: Option<&[i32]>) -> Vec<&str> {
+            let skins = match skins {
+                Some(skins) if !self.skin_families.is_empty() => skins,
+                _ => return self.textures.iter().map(String::as_str).collect(),
             };
-            let self_file_stem = match self_name_path.file_stem() {
-                Some(r) => r,
-                None => {
-                    eprintln!("Не удалось stem файла!");
-                    return;
+            let mut texture_ids: Vec<usize> = Vec::new();
+            for &skin in skins {
+                let family = usize::try_from(skin).ok().and_then(|skin| self.skin_families.get(skin)).unwrap_or(&self.skin_families[0]);
+                for &texture_id in family {
+                    if let Ok(texture_id) = usize::try_from(texture_id)
+                        && !texture_ids.contains(&texture_id)
+                    {
+                        texture_ids.push(texture_id);
+                    }
                 }
-            };
+            }
+            texture_ids.into_iter().filter_map(|texture_id| self.textures.get(texture_id)).map(String::as_str).collect()
+        }
 
-            let find_mdl_dir = find_mdl_path.join(self_name_parent);
-            let output_mdl_dir = output_mdls_path.join(self_name_parent);
+        // Where the model lives in a content folder, going by the name it was compiled with.
+        pub fn content_path(&self) -> String {
+            format!("models/{}", clean_path(&self.name))
+        }
 
-            if let Err(err) = fs::create_dir_all(&output_mdl_dir) {
-                eprintln!("2: {}", err);
-                return;
-            }
-            if let Err(err) = fs::create_dir_all(&output_mats_path) {
-                eprintln!("3: {}", err);
-                return;
+        // Collects the model itself under its content path along with everything it depends on.
+        pub fn resolve(&self, file_system: &dyn FileSystem, referrer: &Referrer, context: &mut ResolveContext, content_set: &mut ContentSet) -> Result<()> {
+            let content_path = self.content_path();
+            let source = file_system.locate(&content_path);
+            content_set.insert(&content_path, AssetKind::Model, referrer.clone(), source);
+            if !context.claim_skins(&content_path, None) {
+                return Ok(());
             }
+            self.resolve_skins(file_system, &content_path, None, context, content_set)
+        }
 
-            let _ = &self.dirs.iter().for_each(|tex_dir_str| {
-                let tex_dir_path: PathBuf = find_mats_path.join(tex_dir_str);
-                if !tex_dir_path.exists() {
-                    eprintln!("Директория не существует: {}", tex_dir_str);
-                    return;
+        // `content_path` is where the model was found, which decides where its companions are looked
+        // up; models moved after compiling no longer match their internal name.
+        pub fn resolve_skins(&self, file_system: &dyn FileSystem, content_path: &str, skins: Option<&[i32]>, context: &mut ResolveContext, content_set: &mut ContentSet) -> Result<()> {
+            let referrer = &Referrer::Asset(content_path.to_string());
+            let content_path = clean_path(content_path);
+            let (rel_mdl_dir, mdl_file_name) = content_path.rsplit_once('/').unwrap_or(("", &content_path));
+            let mdl_stem = Path::new(mdl_file_name).file_stem()
+                .ok_or_else(|| Error::malformed("model path has no file stem").with_path(Path::new(&content_path)))?
+                .to_string_lossy();
+
+            for vmt_tex_stem in self.skin_textures(skins) {
+                let vmt_tex_rel_paths: Vec<PathBuf> = self.dirs
+                    .iter()
+                    .map(|tex_dir_str| Path::new(&clean_path(tex_dir_str)).join(format!("{}.vmt", vmt_tex_stem)))
+                    .collect();
+                let vmt_tex_rel_path = vmt_tex_rel_paths
+                    .iter()
+                    .find(|rel_path| file_system.exists(&format!("materials/{}", rel_path.to_string_lossy())))
+                    .or(vmt_tex_rel_paths.first());
+
+                // A broken material is recorded on its own and does not stop the rest of the model.
+                if let Some(vmt_tex_rel_path) = vmt_tex_rel_path
+                    && let Err(err) = resolve_material(file_system, vmt_tex_rel_path, referrer.clone(), content_set)
+                {
+                    content_set.add_error(&err);
                 }
+            }
 
-                let _ = &self.textures.iter().for_each(|vmt_tex_stem| {
-                    let vmt_tex_rel_path = Path::new(tex_dir_str).join(format!("{}.vmt", vmt_tex_stem));
-                    let vmt_info = match VMTInfo::new(&vmt_tex_rel_path, &find_mats_path) {
-                        Some(r) => r,
-                        None => {
-                            return;
-                        },
-                    };
-
-                    vmt_info.download_with_def_keys(&find_mats_path, &output_mats_path);
-                });
-            });
-
-            let find_mdl_dir_content = match fs::read_dir(find_mdl_dir) {
-                Ok(r) => r,
-                Err(err) => {
-                    eprintln!("4: {}", err);
-                    return;
-                }
+            let companion_path = |suffix: &str| match rel_mdl_dir {
+                "" => format!("{}{}", mdl_stem, suffix),
+                rel_mdl_dir => format!("{}/{}{}", rel_mdl_dir, mdl_stem, suffix),
             };
+            let mut found_suffixes: Vec<&str> = Vec::new();
 
-            find_mdl_dir_content
-                .filter_map(|dir_entry| {
-                    let entry = match dir_entry {
-                        Ok(r) => r,
-                        Err(err) => {
-                            eprintln!("5: {}", err);
-                            return None;
-                        },
-                    };
-                    let entry_path = entry.path();
-
-                    if !entry_path.is_file() {
-                        return None;
+            for suffix in MODEL_COMPANION_SUFFIXES {
+                let rel_path = match file_system.find_name(&companion_path(suffix)) {
+                    Some(r) => r,
+                    None => continue,
+                };
+                found_suffixes.push(suffix);
+                if content_set.contains(&rel_path) {
+                    continue;
+                }
+                let source = file_system.locate(&rel_path);
+                content_set.insert(&rel_path, AssetKind::ModelCompanion, referrer.clone(), source);
+
+                match read_companion_checksum(file_system, &rel_path) {
+                    Ok(checksum) if checksum == self.checksum => {}
+                    Ok(_) => content_set.mark_stale(&rel_path),
+                    Err(err) => {
+                        content_set.mark_broken(&rel_path, &err);
+                        content_set.mark_stale(&rel_path);
                     }
+                }
+            }
 
-                    let file_name = entry_path.file_name()?.to_string_lossy().to_lowercase();
-                    let self_stem = self_file_stem.to_string_lossy().to_lowercase();
-                    
-                    if !file_name.contains(&self_stem) {
-                        return None;
-                    }
-                
-                    Some(entry_path)
-                })
-                .for_each(|input_file_path_buf| {
-                    let file_name = match input_file_path_buf.file_name() {
-                        Some(r) => r,
-                        None => {
-                            let path_str = match input_file_path_buf.to_str() {
-                                Some(r) => r,
-                                None => "?",
-                            };
-                            eprintln!("Не удалось получить OsStr file_name: {}", path_str);
-                            return;
-                        }
-                    };
-
-                    let file_name_str = match file_name.to_str() {
-                        Some(r) => r,
-                        None => {
-                            let path_str = match input_file_path_buf.to_str() {
-                                Some(r) => r,
-                                None => "?",
-                            };
-                            eprintln!("Не преобразовать OsStr в str file_name: {}", path_str);
-                            return;
-                        }
-                    };
-
-                    let output_file_path = output_mdl_dir.join(file_name_str);
-                    let _ = fs::copy(input_file_path_buf, output_file_path);
-                });
+            // A model with meshes cannot load without its vertex data and at least one strip file,
+            // so those are reported missing; the others are optional.
+            if self.bodypart_count > 0 {
+                let has_vtx = found_suffixes.iter().any(|suffix| suffix.ends_with(VTX_SUFFIX));
+                let missing_suffixes = [
+                    (!found_suffixes.contains(&VVD_SUFFIX)).then_some(VVD_SUFFIX),
+                    (!has_vtx).then_some(DEFAULT_VTX_SUFFIX),
+                ];
+                for suffix in missing_suffixes.into_iter().flatten() {
+                    content_set.insert(&companion_path(suffix), AssetKind::ModelCompanion, referrer.clone(), None);
+                }
+            }
+
+            // The content set doubles as the visited list, so include cycles stop at the first repeat.
+            for include_model in &self.include_models {
+                let include_path = clean_path(include_model);
+                let include_path = if include_path.to_lowercase().starts_with("models/") {
+                    include_path
+                } else {
+                    format!("models/{}", include_path)
+                };
+                if let Err(err) = resolve_model(file_system, Path::new(&include_path), referrer.clone(), context, content_set) {
+                    content_set.add_error(&err);
+                }
+            }
+
+            if let Some(err) = &self.keyvalue_error {
+                content_set.mark_broken(&content_path, err);
+            }
+            if let Some(keyvalues) = self.keyvalues() {
+                resolve_prop_data(file_system, keyvalues, referrer, context, content_set);
+            }
+
+            Ok(())
+        }
+
+        pub fn download(&self, file_system: &dyn FileSystem, baseline: &Baseline, writer: &mut dyn ContentWriter) -> Result<ContentSet> {
+            let mut content_set = ContentSet::new();
+            self.resolve(file_system, &Referrer::Input, &mut ResolveContext::new(), &mut content_set)?;
+            content_set.mark_stock(baseline, file_system);
+            content_set.write_to(file_system, writer)?;
+            writer.finish()?;
+            Ok(content_set)
         }
     }
+
+    pub fn resolve_material(file_system: &dyn FileSystem, vmt_rel_path: &Path, referrer: Referrer, content_set: &mut ContentSet) -> Result<()> {
+        let content_path = format!("materials/{}", clean_path(&path_to_string(vmt_rel_path)?));
+        let source = file_system.locate(&content_path);
+        let found = source.is_some();
+
+        if !content_set.insert(&content_path, AssetKind::Material, referrer, source) || !found {
+            return Ok(());
+        }
+
+        let result = VMTInfo::new(vmt_rel_path, file_system)
+            .and_then(|vmt_info| vmt_info.resolve_with_def_keys(file_system, content_set));
+        if let Err(err) = result {
+            content_set.mark_broken(&content_path, &err);
+        }
+        Ok(())
+    }
+
+    pub fn resolve_model(file_system: &dyn FileSystem, mdl_rel_path: &Path, referrer: Referrer, context: &mut ResolveContext, content_set: &mut ContentSet) -> Result<()> {
+        resolve_model_skins(file_system, mdl_rel_path, referrer, None, context, content_set)
+    }
+
+    // Only pulls in the materials of `skins`. A model met again with other skins is parsed again
+    // so their materials are added too; `None` means every skin.
+    pub fn resolve_model_skins(file_system: &dyn FileSystem, mdl_rel_path: &Path, referrer: Referrer, skins: Option<&[i32]>, context: &mut ResolveContext, content_set: &mut ContentSet) -> Result<()> {
+        let content_path = clean_path(&path_to_string(mdl_rel_path)?);
+        let source = file_system.locate(&content_path);
+        let found = source.is_some();
+
+        content_set.insert(&content_path, AssetKind::Model, referrer, source);
+        if !found || !context.claim_skins(&content_path, skins) {
+            return Ok(());
+        }
+
+        // A model that does not parse is still copied; the failure is recorded on its entry.
+        let result = file_system.read(&content_path)
+            .and_then(|mdl_bytes| TexturesInfo::from_bytes(mdl_bytes, Path::new(&content_path)))
+            .and_then(|textures_info| textures_info.resolve_skins(file_system, &content_path, skins, context, content_set));
+        if let Err(err) = result {
+            content_set.mark_broken(&content_path, &err);
+        }
+        Ok(())
+    }
+
+    fn path_to_string(path: &Path) -> Result<String> {
+        path.to_str()
+            .map(String::from)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidUtf8).with_path(path))
+    }
 }
+
+pub mod baseline;
+pub mod content;
+pub mod fastdl;
+pub mod gameinfo;
+pub mod gma;
+pub mod lua;
+pub mod output;
+pub mod propdata;
+pub mod report;
+pub mod search;
+pub mod vfs;
+pub mod vpk;
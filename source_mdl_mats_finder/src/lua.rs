@@ -0,0 +1,36 @@
+use std::collections::HashSet;
+use simple_utils::utils::normalize_name;
+use crate::content::{AssetKind, ContentSet};
+
+fn lua_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// resource.AddFile pulls in a model's .vvd/.vtx/.phy and the .vtf named like a .vmt on its own,
+// so only root files are listed; a texture only gets its own line when no material shares its name.
+pub fn build_resource_lua(content_set: &ContentSet, workshop_id: Option<&str>) -> String {
+    if let Some(workshop_id) = workshop_id {
+        return format!("resource.AddWorkshop({})\n", lua_string(workshop_id));
+    }
+
+    let material_stems: HashSet<String> = content_set
+        .to_copy()
+        .filter(|entry| entry.kind == AssetKind::Material)
+        .filter_map(|entry| Some(normalize_name(&entry.path).strip_suffix(".vmt")?.to_string()))
+        .collect();
+
+    let mut text = String::new();
+    for entry in content_set.to_copy() {
+        let is_root = match entry.kind {
+            AssetKind::Model | AssetKind::Material | AssetKind::Sound => true,
+            AssetKind::Texture => normalize_name(&entry.path)
+                .strip_suffix(".vtf")
+                .is_none_or(|stem| !material_stems.contains(stem)),
+            AssetKind::ModelCompanion | AssetKind::Script | AssetKind::Other => false,
+        };
+        if is_root {
+            text.push_str(&format!("resource.AddFile({})\n", lua_string(&entry.path)));
+        }
+    }
+    text
+}
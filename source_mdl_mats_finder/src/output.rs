@@ -0,0 +1,169 @@
+use std::{fs, path::{Path, PathBuf}, collections::HashSet};
+use simple_utils::utils::{normalize_name, safe_rel_path};
+use simple_utils::error::{Result, ResultExt};
+use simple_utils::zip::ZipWriter;
+use crate::gma::{GmaWriter, is_whitelisted};
+use crate::vpk::{DEFAULT_CHUNK_SIZE, VpkWriter};
+
+// Where collected content ends up: a loose folder or a single archive.
+pub trait ContentWriter {
+    fn add_file(&mut self, rel_path: &str, contents: Vec<u8>) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+
+    // Files the output format does not allow and were left out.
+    fn rejected(&self) -> &[String] {
+        &[]
+    }
+}
+
+#[derive(Debug)]
+pub struct DirWriter {
+    root: PathBuf,
+}
+
+impl DirWriter {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl ContentWriter for DirWriter {
+    fn add_file(&mut self, rel_path: &str, contents: Vec<u8>) -> Result<()> {
+        let output_file_path = self.root.join(safe_rel_path(rel_path)?);
+        if let Some(parent_dir) = output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        fs::write(&output_file_path, contents).with_path(&output_file_path)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ZipFileWriter {
+    output_file_path: PathBuf,
+    pub deflate: bool,
+    writer: ZipWriter,
+    names: HashSet<String>,
+}
+
+impl ZipFileWriter {
+    pub fn new(output_file_path: &Path) -> Self {
+        Self {
+            output_file_path: output_file_path.to_path_buf(),
+            deflate: false,
+            writer: ZipWriter::new(),
+            names: HashSet::new(),
+        }
+    }
+}
+
+impl ContentWriter for ZipFileWriter {
+    fn add_file(&mut self, rel_path: &str, contents: Vec<u8>) -> Result<()> {
+        let rel_path = &safe_rel_path(rel_path)?;
+        if !self.names.insert(normalize_name(rel_path)) {
+            return Ok(());
+        }
+        if self.deflate {
+            return self.writer.add_file_deflated(rel_path, &contents);
+        }
+        self.writer.add_file(rel_path, &contents)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(parent_dir) = self.output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        let zip_bytes = std::mem::take(&mut self.writer).finish().with_path(&self.output_file_path)?;
+        fs::write(&self.output_file_path, zip_bytes).with_path(&self.output_file_path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Dir,
+    Gma,
+    Vpk,
+    Zip,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dir" => Some(OutputFormat::Dir),
+            "gma" => Some(OutputFormat::Gma),
+            "vpk" => Some(OutputFormat::Vpk),
+            "zip" => Some(OutputFormat::Zip),
+            _ => None,
+        }
+    }
+
+    // Whether the format's writer takes the file, the same check its add_file makes.
+    pub fn allows(&self, rel_path: &str) -> bool {
+        match self {
+            OutputFormat::Gma => is_whitelisted(rel_path),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    // Workshop addon type and tags, only stored by the GMA format.
+    pub addon_type: String,
+    pub tags: Vec<String>,
+    pub chunk_size: u64,
+    pub preload_size: usize,
+    pub deflate: bool,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            description: String::new(),
+            author: String::new(),
+            addon_type: String::new(),
+            tags: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            preload_size: 0,
+            deflate: false,
+        }
+    }
+}
+
+pub fn create_writer(format: OutputFormat, output_path: &Path, output_options: &OutputOptions) -> Box<dyn ContentWriter> {
+    match format {
+        OutputFormat::Dir => Box::new(DirWriter::new(output_path)),
+        OutputFormat::Gma => {
+            let mut writer = GmaWriter::new(output_path, &output_options.title);
+            writer.description = output_options.description.clone();
+            writer.author = output_options.author.clone();
+            if !output_options.addon_type.is_empty() {
+                writer.addon_type = output_options.addon_type.clone();
+            }
+            writer.tags = output_options.tags.clone();
+            Box::new(writer)
+        }
+        OutputFormat::Vpk => {
+            let mut writer = VpkWriter::new(output_path);
+            writer.chunk_size = output_options.chunk_size;
+            writer.preload_size = output_options.preload_size;
+            Box::new(writer)
+        }
+        OutputFormat::Zip => {
+            let mut writer = ZipFileWriter::new(output_path);
+            writer.deflate = output_options.deflate;
+            Box::new(writer)
+        }
+    }
+}
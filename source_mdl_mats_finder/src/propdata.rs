@@ -0,0 +1,120 @@
+use std::path::Path;
+use simple_utils::utils::clean_path;
+use simple_utils::error::{Result, ResultExt};
+use crate::content::{AssetKind, ContentSet, Referrer, ResolveContext};
+use crate::finder::resolve_model;
+use crate::gameinfo::{KvValue, parse_keyvalues};
+use crate::vfs::FileSystem;
+
+pub const PROPDATA_MANIFEST: &str = "scripts/propdata.txt";
+const BREAKABLE_MODELS_KEY: &str = "BreakableModels";
+
+// The prop types and gib sets from every file listed in scripts/propdata.txt.
+#[derive(Debug, Default)]
+pub struct PropData {
+    files: Vec<(String, KvValue)>,
+}
+
+fn is_block(value: &KvValue) -> bool {
+    matches!(value, KvValue::Block(_))
+}
+
+fn read_keyvalues(file_system: &dyn FileSystem, rel_path: &str) -> Result<KvValue> {
+    let text = file_system.read(rel_path)?;
+    parse_keyvalues(&String::from_utf8_lossy(&text)).with_path(Path::new(rel_path))
+}
+
+impl PropData {
+    pub fn load(file_system: &dyn FileSystem) -> Result<Self> {
+        let mut prop_data = Self::default();
+        if !file_system.exists(PROPDATA_MANIFEST) {
+            return Ok(prop_data);
+        }
+
+        let manifest = read_keyvalues(file_system, PROPDATA_MANIFEST)?;
+        let file_paths = manifest.pairs()
+            .iter()
+            .flat_map(|(_, block)| block.pairs())
+            .filter(|(key, _)| key.eq_ignore_ascii_case("file"))
+            .filter_map(|(_, value)| value.as_str())
+            .map(clean_path);
+
+        for file_path in file_paths {
+            if !file_system.exists(&file_path) {
+                continue;
+            }
+            let root = read_keyvalues(file_system, &file_path)?;
+            for (_, block) in root.pairs().iter().filter(|(_, block)| is_block(block)) {
+                prop_data.files.push((file_path.clone(), block.clone()));
+            }
+        }
+
+        Ok(prop_data)
+    }
+
+    // Later files override earlier ones, like the engine's load order.
+    pub fn prop_type(&self, name: &str) -> Option<(&str, &KvValue)> {
+        self.files
+            .iter()
+            .rev()
+            .find_map(|(file_path, block)| Some((file_path.as_str(), block.get(name).filter(|value| is_block(value))?)))
+    }
+
+    pub fn breakable_models(&self, name: &str) -> Option<(&str, Vec<&str>)> {
+        self.files.iter().rev().find_map(|(file_path, block)| {
+            let gib_set = block.get(BREAKABLE_MODELS_KEY)?.get(name)?;
+            Some((file_path.as_str(), gib_set.pairs().iter().map(|(model, _)| model.as_str()).collect()))
+        })
+    }
+}
+
+fn model_values(block: &KvValue) -> impl Iterator<Item = &str> {
+    block.pairs()
+        .iter()
+        .filter_map(|(_, value)| value.as_str())
+        .filter(|value| value.to_lowercase().ends_with(".mdl"))
+}
+
+fn insert_script(file_system: &dyn FileSystem, rel_path: &str, referrer: &Referrer, content_set: &mut ContentSet) {
+    let source = file_system.locate(rel_path);
+    content_set.insert(rel_path, AssetKind::Script, referrer.clone(), source);
+}
+
+// Pulls in the gibs a model's prop_data block breaks into. `breakable_model` names a gib set from
+// the propdata scripts (or is a model path itself) and may be inherited from the `base` prop type;
+// `breakable_count` only caps how many spawn, so every model of the set is needed.
+pub fn resolve_prop_data(file_system: &dyn FileSystem, model_keyvalues: &KvValue, referrer: &Referrer, context: &mut ResolveContext, content_set: &mut ContentSet) {
+    let mut gib_models: Vec<String> = Vec::new();
+    let blocks = ["prop_data", "physgun_interactions"].map(|key| model_keyvalues.get(key));
+    for block in blocks.iter().flatten() {
+        gib_models.extend(model_values(block).map(String::from));
+    }
+
+    if let Some(prop_data_block) = blocks[0] {
+        let breakable_model = prop_data_block.get("breakable_model").and_then(|value| value.as_str());
+        let base = prop_data_block.get("base").and_then(|value| value.as_str());
+
+        if breakable_model.is_some_and(|name| !name.to_lowercase().ends_with(".mdl")) || (breakable_model.is_none() && base.is_some()) {
+            let prop_data = context.prop_data(file_system, content_set);
+            let mut gib_set = breakable_model.map(String::from);
+
+            if let Some((file_path, prop_type)) = base.and_then(|base| prop_data.prop_type(base)) {
+                insert_script(file_system, file_path, referrer, content_set);
+                gib_models.extend(model_values(prop_type).map(String::from));
+                if gib_set.is_none() {
+                    gib_set = prop_type.get("breakable_model").and_then(|value| value.as_str()).map(String::from);
+                }
+            }
+            if let Some((file_path, models)) = gib_set.and_then(|name| prop_data.breakable_models(&name)) {
+                insert_script(file_system, file_path, referrer, content_set);
+                gib_models.extend(models.into_iter().map(String::from));
+            }
+        }
+    }
+
+    for gib_model in gib_models {
+        if let Err(err) = resolve_model(file_system, Path::new(&clean_path(&gib_model)), referrer.clone(), context, content_set) {
+            content_set.add_error(&err);
+        }
+    }
+}
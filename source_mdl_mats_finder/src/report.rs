@@ -0,0 +1,226 @@
+use std::{collections::BTreeMap, fmt::Write};
+use simple_utils::utils::json_escape;
+use crate::content::{AssetKind, ContentSet, Referrer};
+use crate::output::OutputFormat;
+use crate::vfs::FileSystem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl ReportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(ReportFormat::Text),
+            "json" => Some(ReportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MissingReport {
+    source: String,
+    groups: BTreeMap<Referrer, Vec<(String, AssetKind)>>,
+    sources: BTreeMap<String, Vec<String>>,
+    stock: Vec<String>,
+    stale: Vec<String>,
+    broken: Vec<(String, String)>,
+    errors: Vec<String>,
+    missing_count: usize,
+}
+
+impl MissingReport {
+    pub fn new(source: &str, content_set: &ContentSet) -> Self {
+        let mut groups: BTreeMap<Referrer, Vec<(String, AssetKind)>> = BTreeMap::new();
+        let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut missing_count = 0;
+
+        for entry in content_set.to_copy() {
+            if let Some(source) = &entry.source {
+                sources.entry(source.clone()).or_default().push(entry.path.clone());
+            }
+        }
+
+        for entry in content_set.missing() {
+            missing_count += 1;
+            for referrer in &entry.referenced_by {
+                groups.entry(referrer.clone()).or_default().push((entry.path.clone(), entry.kind));
+            }
+        }
+
+        Self {
+            source: source.to_string(),
+            groups,
+            sources,
+            stock: content_set.stock().map(|entry| entry.path.clone()).collect(),
+            stale: content_set.stale().map(|entry| entry.path.clone()).collect(),
+            broken: content_set.broken()
+                .map(|entry| (entry.path.clone(), entry.error.clone().unwrap_or_default()))
+                .collect(),
+            errors: content_set.errors().to_vec(),
+            missing_count,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing_count == 0
+    }
+
+    pub fn missing_count(&self) -> usize {
+        self.missing_count
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_text(),
+            ReportFormat::Json => self.to_json(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Missing content for {}: {} file(s)\n", self.source, self.missing_count);
+        for (referrer, assets) in &self.groups {
+            let _ = writeln!(text, "\n{}:", referrer);
+            for (path, kind) in assets {
+                let _ = writeln!(text, "  {:<16} {}", kind.as_str(), path);
+            }
+        }
+        if !self.sources.is_empty() {
+            let _ = writeln!(text, "\nFound content by search path:");
+        }
+        for (source, paths) in &self.sources {
+            let _ = writeln!(text, "\n{}:", source);
+            for path in paths {
+                let _ = writeln!(text, "  {}", path);
+            }
+        }
+        if !self.stock.is_empty() {
+            let _ = writeln!(text, "\nStock content (excluded): {} file(s)", self.stock.len());
+        }
+        for path in &self.stock {
+            let _ = writeln!(text, "  {}", path);
+        }
+        if !self.stale.is_empty() {
+            let _ = writeln!(text, "\nStale model companions (checksum mismatch, not copied): {} file(s)", self.stale.len());
+        }
+        for path in &self.stale {
+            let _ = writeln!(text, "  {}", path);
+        }
+        if !self.broken.is_empty() {
+            let _ = writeln!(text, "\nBroken content (copied, dependencies not collected): {} file(s)", self.broken.len());
+        }
+        for (path, error) in &self.broken {
+            let _ = writeln!(text, "  {}: {}", path, error);
+        }
+        if !self.errors.is_empty() {
+            let _ = writeln!(text, "\nResolve errors: {}", self.errors.len());
+        }
+        for error in &self.errors {
+            let _ = writeln!(text, "  {}", error);
+        }
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let groups = self.groups
+            .iter()
+            .map(|(referrer, assets)| {
+                let assets = assets
+                    .iter()
+                    .map(|(path, kind)| format!("{{\"path\":{},\"kind\":{}}}", json_escape(path), json_escape(kind.as_str())))
+                    .collect::<Vec<String>>()
+                    .join(",");
+                format!("{{\"referrer\":{},\"missing\":[{}]}}", referrer_to_json(referrer), assets)
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let sources = self.sources
+            .iter()
+            .map(|(source, paths)| {
+                let paths = paths.iter().map(|path| json_escape(path)).collect::<Vec<String>>().join(",");
+                format!("{{\"search_path\":{},\"files\":[{}]}}", json_escape(source), paths)
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let stock = self.stock.iter().map(|path| json_escape(path)).collect::<Vec<String>>().join(",");
+        let stale = self.stale.iter().map(|path| json_escape(path)).collect::<Vec<String>>().join(",");
+        let broken = self.broken
+            .iter()
+            .map(|(path, error)| format!("{{\"path\":{},\"error\":{}}}", json_escape(path), json_escape(error)))
+            .collect::<Vec<String>>()
+            .join(",");
+        let errors = self.errors.iter().map(|error| json_escape(error)).collect::<Vec<String>>().join(",");
+        format!(
+            "{{\"source\":{},\"missing_count\":{},\"groups\":[{}],\"found\":[{}],\"stock\":[{}],\"stale\":[{}],\"broken\":[{}],\"errors\":[{}]}}",
+            json_escape(&self.source),
+            self.missing_count,
+            groups,
+            sources,
+            stock,
+            stale,
+            broken,
+            errors,
+        )
+    }
+}
+
+fn referrer_to_json(referrer: &Referrer) -> String {
+    match referrer {
+        Referrer::Input => "{\"type\":\"input\"}".to_string(),
+        Referrer::Map => "{\"type\":\"map\"}".to_string(),
+        Referrer::Entity { index, classname } => {
+            format!("{{\"type\":\"entity\",\"index\":{},\"classname\":{}}}", index, json_escape(classname))
+        }
+        Referrer::StaticProp { index } => format!("{{\"type\":\"static_prop\",\"index\":{}}}", index),
+        Referrer::Asset(path) => {
+            format!("{{\"type\":{},\"path\":{}}}", json_escape(AssetKind::from_path(path).as_str()), json_escape(path))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CopyPlan {
+    files: Vec<(String, u64, String)>,
+}
+
+impl CopyPlan {
+    pub fn new(content_set: &ContentSet, file_system: &dyn FileSystem) -> Self {
+        let mut plan = Self::default();
+        for entry in content_set.to_copy() {
+            let size = file_system.size(&entry.path).unwrap_or(0);
+            plan.add(&entry.path, size, entry.source.as_deref().unwrap_or_default());
+        }
+        plan
+    }
+
+    pub fn add(&mut self, path: &str, size: u64, source: &str) {
+        self.files.push((path.to_string(), size, source.to_string()));
+    }
+
+    // Drops the files the output format would skip and returns their paths.
+    pub fn reject(&mut self, output_format: OutputFormat) -> Vec<String> {
+        let (allowed, rejected) = self.files.drain(..).partition(|(path, _, _)| output_format.allows(path));
+        self.files = allowed;
+        rejected.into_iter().map(|(path, _, _)| path).collect()
+    }
+
+    pub fn files(&self) -> &[(String, u64, String)] {
+        &self.files
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|(_, size, _)| size).sum()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (path, size, source) in &self.files {
+            let _ = writeln!(text, "{:>12} {}  ({})", size, path, source);
+        }
+        let _ = write!(text, "{} file(s), {} bytes total", self.files.len(), self.total_bytes());
+        text
+    }
+}
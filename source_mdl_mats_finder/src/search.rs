@@ -0,0 +1,157 @@
+use std::{fs, io::Read, path::{Path, PathBuf}, collections::HashSet};
+use simple_utils::utils::normalize_name;
+use simple_utils::error::{Error, Result, ResultExt};
+use crate::gameinfo::GameInfo;
+use crate::gma::GmaArchive;
+use crate::vfs::{FileSystem, LooseDir};
+use crate::vpk::VpkArchive;
+
+// Layers are searched in order; the first one that has a file wins.
+#[derive(Debug, Default)]
+pub struct SearchPaths {
+    layers: Vec<Box<dyn FileSystem>>,
+    // Archives that failed to open; one bad download should not hide every other search path.
+    skipped: Vec<String>,
+}
+
+impl SearchPaths {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Accepts either a content folder or a gameinfo.txt describing the full search order.
+    pub fn open(find_path: &Path) -> Result<Self> {
+        if find_path.is_file() {
+            return GameInfo::open(find_path)?.mount();
+        }
+        Self::discover(find_path)
+    }
+
+    pub fn discover(find_path: &Path) -> Result<Self> {
+        let mut search_paths = Self::new();
+        search_paths.add(LooseDir::new(find_path));
+
+        let mut vpk_paths: Vec<PathBuf> = fs::read_dir(find_path)
+            .with_path(find_path)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_file() && path.to_string_lossy().to_lowercase().ends_with("_dir.vpk"))
+            .collect();
+        vpk_paths.sort();
+
+        for vpk_path in vpk_paths {
+            search_paths.add_vpk(&vpk_path);
+        }
+        search_paths.add_gma_dir(find_path)?;
+        Ok(search_paths)
+    }
+
+    // Mounts every readable .gma in a folder such as garrysmod/addons or the workshop cache and
+    // returns how many were mounted.
+    pub fn add_gma_dir(&mut self, dir_path: &Path) -> Result<usize> {
+        let mut gma_paths: Vec<PathBuf> = fs::read_dir(dir_path)
+            .with_path(dir_path)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gma")))
+            .collect();
+        gma_paths.sort();
+
+        Ok(gma_paths.iter().filter(|gma_path| self.add_gma(gma_path)).count())
+    }
+
+    // Mounts the archive, or records why it could not be read and returns false.
+    pub fn add_vpk(&mut self, vpk_path: &Path) -> bool {
+        match VpkArchive::open(vpk_path) {
+            Ok(vpk) => {
+                self.add(vpk);
+                true
+            }
+            Err(err) => {
+                self.skipped.push(err.to_string());
+                false
+            }
+        }
+    }
+
+    pub fn add_gma(&mut self, gma_path: &Path) -> bool {
+        match GmaArchive::open(gma_path) {
+            Ok(gma) => {
+                self.add(gma);
+                true
+            }
+            Err(err) => {
+                self.skipped.push(err.to_string());
+                false
+            }
+        }
+    }
+
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    pub fn add(&mut self, layer: impl FileSystem + 'static) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub fn add_boxed(&mut self, layer: Box<dyn FileSystem>) {
+        self.layers.push(layer);
+    }
+
+    pub fn layers(&self) -> &[Box<dyn FileSystem>] {
+        &self.layers
+    }
+
+    pub fn find_layer(&self, rel_path: &str) -> Option<&dyn FileSystem> {
+        self.layers.iter().find(|layer| layer.exists(rel_path)).map(|layer| layer.as_ref())
+    }
+}
+
+impl FileSystem for SearchPaths {
+    fn describe(&self) -> String {
+        self.layers.iter().map(|layer| layer.describe()).collect::<Vec<String>>().join(";")
+    }
+
+    fn locate(&self, rel_path: &str) -> Option<String> {
+        self.find_layer(rel_path).map(|layer| layer.describe())
+    }
+
+    fn exists(&self, rel_path: &str) -> bool {
+        self.find_layer(rel_path).is_some()
+    }
+
+    fn size(&self, rel_path: &str) -> Option<u64> {
+        self.layers.iter().find_map(|layer| layer.size(rel_path))
+    }
+
+    fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+        match self.find_layer(rel_path) {
+            Some(layer) => layer.read(rel_path),
+            None => Err(Error::not_found(Path::new(rel_path))),
+        }
+    }
+
+    fn list(&self, rel_dir: &str) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut files: Vec<String> = self.layers.iter().flat_map(|layer| layer.list(rel_dir)).collect();
+        files.retain(|path| seen.insert(normalize_name(path)));
+        files
+    }
+
+    fn find_name(&self, rel_path: &str) -> Option<String> {
+        self.layers.iter().find_map(|layer| layer.find_name(rel_path))
+    }
+
+    fn open(&self, rel_path: &str) -> Result<Box<dyn Read + '_>> {
+        match self.find_layer(rel_path) {
+            Some(layer) => layer.open(rel_path),
+            None => Err(Error::not_found(Path::new(rel_path))),
+        }
+    }
+
+    fn copy_file(&self, rel_path: &str, output_file_path: &Path) -> Result<()> {
+        match self.find_layer(rel_path) {
+            Some(layer) => layer.copy_file(rel_path, output_file_path),
+            None => Err(Error::not_found(Path::new(rel_path))),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::{fmt, fs, io::{Cursor, Read}, path::{Path, PathBuf}, collections::HashMap};
+use simple_utils::utils::{clean_path, normalize_name, safe_rel_path};
+use simple_utils::error::{Error, Result, ResultExt};
+use simple_utils::zip::ZipArchive;
+use crate::gma::GmaArchive;
+use crate::vpk::VpkArchive;
+
+pub trait FileSystem: fmt::Debug {
+    fn describe(&self) -> String;
+    fn exists(&self, rel_path: &str) -> bool;
+    fn size(&self, rel_path: &str) -> Option<u64>;
+    fn read(&self, rel_path: &str) -> Result<Vec<u8>>;
+    fn list(&self, rel_dir: &str) -> Vec<String>;
+
+    // The path to read a file under when its case may differ from `rel_path`. Archives look names
+    // up case-insensitively already; only loose folders have to search.
+    fn find_name(&self, rel_path: &str) -> Option<String> {
+        self.exists(rel_path).then(|| rel_path.to_string())
+    }
+
+    // Names the search path a file would be read from, for reports.
+    fn locate(&self, rel_path: &str) -> Option<String> {
+        self.exists(rel_path).then(|| self.describe())
+    }
+
+    fn open(&self, rel_path: &str) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(Cursor::new(self.read(rel_path)?)))
+    }
+
+    fn copy_file(&self, rel_path: &str, output_file_path: &Path) -> Result<()> {
+        let data = self.read(rel_path)?;
+        if let Some(parent_dir) = output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        fs::write(output_file_path, data).with_path(output_file_path)
+    }
+}
+
+pub fn in_dir(rel_path: &str, rel_dir: &str) -> bool {
+    let rel_path = normalize_name(rel_path);
+    let rel_dir = normalize_name(rel_dir);
+    rel_path.rsplit_once('/').map_or("", |(parent, _)| parent) == rel_dir.trim_end_matches('/')
+}
+
+#[derive(Debug)]
+pub struct LooseDir {
+    root: PathBuf,
+}
+
+impl LooseDir {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Entity values such as `models/../../x` must not reach files outside the folder.
+    fn full_path(&self, rel_path: &str) -> Result<PathBuf> {
+        Ok(self.root.join(safe_rel_path(rel_path)?))
+    }
+}
+
+impl FileSystem for LooseDir {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn exists(&self, rel_path: &str) -> bool {
+        self.full_path(rel_path).is_ok_and(|full_path| full_path.is_file())
+    }
+
+    fn size(&self, rel_path: &str) -> Option<u64> {
+        fs::metadata(self.full_path(rel_path).ok()?).ok().filter(|meta| meta.is_file()).map(|meta| meta.len())
+    }
+
+    fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+        let full_path = self.full_path(rel_path)?;
+        fs::read(&full_path).with_path(&full_path)
+    }
+
+    fn list(&self, rel_dir: &str) -> Vec<String> {
+        let rel_dir = clean_path(rel_dir);
+        let dir_content = match self.full_path(&rel_dir).and_then(|full_path| fs::read_dir(&full_path).with_path(&full_path)) {
+            Ok(r) => r,
+            Err(_) => return Vec::new(),
+        };
+
+        dir_content
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|entry_path| entry_path.is_file())
+            .filter_map(|entry_path| {
+                let file_name = entry_path.file_name()?.to_str()?.to_string();
+                Some(match rel_dir.trim_end_matches('/') {
+                    "" => file_name,
+                    rel_dir => format!("{}/{}", rel_dir, file_name),
+                })
+            })
+            .collect()
+    }
+
+    fn find_name(&self, rel_path: &str) -> Option<String> {
+        if self.exists(rel_path) {
+            return Some(rel_path.to_string());
+        }
+        let rel_path = clean_path(rel_path);
+        let (rel_dir, file_name) = rel_path.rsplit_once('/').unwrap_or(("", &rel_path));
+        self.list(rel_dir)
+            .into_iter()
+            .find(|path| path.rsplit('/').next().unwrap_or_default().eq_ignore_ascii_case(file_name))
+    }
+
+    fn open(&self, rel_path: &str) -> Result<Box<dyn Read + '_>> {
+        let full_path = self.full_path(rel_path)?;
+        Ok(Box::new(fs::File::open(&full_path).with_path(&full_path)?))
+    }
+
+    fn copy_file(&self, rel_path: &str, output_file_path: &Path) -> Result<()> {
+        if let Some(parent_dir) = output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        let full_path = self.full_path(rel_path)?;
+        fs::copy(&full_path, output_file_path).with_path(&full_path)?;
+        Ok(())
+    }
+}
+
+impl FileSystem for VpkArchive {
+    fn describe(&self) -> String {
+        self.dir_path().display().to_string()
+    }
+
+    fn exists(&self, rel_path: &str) -> bool {
+        self.contains(rel_path)
+    }
+
+    fn size(&self, rel_path: &str) -> Option<u64> {
+        self.find(rel_path).map(|entry| entry.size())
+    }
+
+    fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+        VpkArchive::read(self, rel_path)
+    }
+
+    fn list(&self, rel_dir: &str) -> Vec<String> {
+        self.entries().filter(|entry| in_dir(&entry.path, rel_dir)).map(|entry| entry.path.clone()).collect()
+    }
+}
+
+impl FileSystem for GmaArchive {
+    fn describe(&self) -> String {
+        self.path().display().to_string()
+    }
+
+    fn exists(&self, rel_path: &str) -> bool {
+        self.contains(rel_path)
+    }
+
+    fn size(&self, rel_path: &str) -> Option<u64> {
+        self.find(rel_path).map(|entry| entry.size)
+    }
+
+    fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+        GmaArchive::read(self, rel_path)
+    }
+
+    fn list(&self, rel_dir: &str) -> Vec<String> {
+        self.entries().filter(|entry| in_dir(&entry.path, rel_dir)).map(|entry| entry.path.clone()).collect()
+    }
+}
+
+impl FileSystem for ZipArchive {
+    fn describe(&self) -> String {
+        "zip archive".to_string()
+    }
+
+    fn exists(&self, rel_path: &str) -> bool {
+        self.contains(rel_path)
+    }
+
+    fn size(&self, rel_path: &str) -> Option<u64> {
+        self.find(rel_path).map(|entry| entry.size as u64)
+    }
+
+    fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+        self.read_by_name(rel_path)
+    }
+
+    fn list(&self, rel_dir: &str) -> Vec<String> {
+        self.entries()
+            .iter()
+            .filter(|entry| !entry.name.ends_with('/') && in_dir(&entry.name, rel_dir))
+            .map(|entry| clean_path(&entry.name))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    files: HashMap<String, (String, Vec<u8>)>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, rel_path: &str, contents: Vec<u8>) {
+        self.files.insert(normalize_name(rel_path), (clean_path(rel_path), contents));
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn exists(&self, rel_path: &str) -> bool {
+        self.files.contains_key(&normalize_name(rel_path))
+    }
+
+    fn size(&self, rel_path: &str) -> Option<u64> {
+        self.files.get(&normalize_name(rel_path)).map(|(_, contents)| contents.len() as u64)
+    }
+
+    fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+        self.files
+            .get(&normalize_name(rel_path))
+            .map(|(_, contents)| contents.clone())
+            .ok_or_else(|| Error::not_found(Path::new(rel_path)))
+    }
+
+    fn list(&self, rel_dir: &str) -> Vec<String> {
+        self.files.values().filter(|(path, _)| in_dir(path, rel_dir)).map(|(path, _)| path.clone()).collect()
+    }
+}
@@ -0,0 +1,338 @@
+use std::{fs::{self, File}, io::Write, path::{Path, PathBuf}, collections::{BTreeMap, HashMap}};
+use simple_utils::utils::{FromSlice, get_slice, normalize_name, read_exact_from_file, safe_rel_path};
+use simple_utils::error::{Error, Result, ResultExt};
+use crate::output::ContentWriter;
+
+const VPK_SIGNATURE: u32 = 0x55aa1234;
+const V1_HEADER_SIZE: usize = 12;
+const V2_HEADER_SIZE: usize = 28;
+const ENTRY_SIZE: usize = 18;
+const ENTRY_TERMINATOR: u16 = 0xffff;
+const DIR_ARCHIVE_INDEX: u16 = 0x7fff;
+const MAX_PRELOAD_SIZE: usize = u16::MAX as usize;
+// The tree stores a single space for a file without a directory or extension.
+const EMPTY_TREE_STR: &str = " ";
+pub const DEFAULT_CHUNK_SIZE: u64 = 200 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct VpkEntry {
+    pub path: String,
+    pub crc32: u32,
+    pub preload: Vec<u8>,
+    pub archive_index: u16,
+    pub entry_offset: u32,
+    pub entry_length: u32,
+}
+
+impl VpkEntry {
+    pub fn size(&self) -> u64 {
+        self.preload.len() as u64 + self.entry_length as u64
+    }
+}
+
+#[derive(Debug)]
+pub struct VpkArchive {
+    dir_path: PathBuf,
+    version: u32,
+    data_ofs: u64,
+    entries: HashMap<String, VpkEntry>,
+}
+
+fn chunk_path(dir_path: &Path, archive_index: u16) -> PathBuf {
+    let file_name = dir_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let prefix = file_name.strip_suffix("_dir.vpk").unwrap_or(&file_name);
+    dir_path.with_file_name(format!("{}_{:03}.vpk", prefix, archive_index))
+}
+
+fn read_tree_str(tree: &[u8], ofs: &mut usize) -> Result<String> {
+    let start = *ofs;
+    let len = tree.get(start..)
+        .and_then(|rest| rest.iter().position(|&b| b == 0))
+        .ok_or_else(|| Error::malformed("unterminated string in VPK tree").with_offset(start as u64))?;
+    *ofs = start + len + 1;
+    String::from_utf8(tree[start..(start + len)].to_vec()).with_offset(start as u64)
+}
+
+impl VpkArchive {
+    pub fn open(dir_path: &Path) -> Result<Self> {
+        let mut f = File::open(dir_path).with_path(dir_path)?;
+        let header_bytes = read_exact_from_file(&mut f, 0, V1_HEADER_SIZE).with_field("VPKHeader").with_path(dir_path)?;
+        if u32::from_bytes_at(&header_bytes, 0).with_path(dir_path)? != VPK_SIGNATURE {
+            return Err(Error::malformed("bad VPK signature").with_field("Signature").with_path(dir_path));
+        }
+        let version = u32::from_bytes_at(&header_bytes, 4).with_field("Version").with_path(dir_path)?;
+        let tree_size = u32::from_bytes_at(&header_bytes, 8).with_field("TreeSize").with_path(dir_path)? as usize;
+        let header_size = match version {
+            1 => V1_HEADER_SIZE,
+            2 => V2_HEADER_SIZE,
+            _ => return Err(Error::unsupported(format!("VPK version {}", version)).with_path(dir_path)),
+        };
+
+        let tree = read_exact_from_file(&mut f, header_size as u64, tree_size).with_field("tree").with_path(dir_path)?;
+        let entries = Self::parse_tree(&tree).with_offset(header_size as u64).with_path(dir_path)?;
+
+        Ok(Self {
+            dir_path: dir_path.to_path_buf(),
+            version,
+            data_ofs: (header_size + tree_size) as u64,
+            entries,
+        })
+    }
+
+    fn parse_tree(tree: &[u8]) -> Result<HashMap<String, VpkEntry>> {
+        let mut entries = HashMap::new();
+        let mut ofs = 0;
+
+        loop {
+            let ext = read_tree_str(tree, &mut ofs).with_field("extension")?;
+            if ext.is_empty() {
+                break;
+            }
+            loop {
+                let dir = read_tree_str(tree, &mut ofs).with_field("path")?;
+                if dir.is_empty() {
+                    break;
+                }
+                loop {
+                    let file_name = read_tree_str(tree, &mut ofs).with_field("filename")?;
+                    if file_name.is_empty() {
+                        break;
+                    }
+
+                    let entry_bytes = get_slice(tree, ofs, ENTRY_SIZE).with_field("VPKDirectoryEntry")?;
+                    if u16::from_bytes_at(entry_bytes, 16)? != ENTRY_TERMINATOR {
+                        return Err(Error::malformed("bad VPK entry terminator").with_offset(ofs as u64));
+                    }
+                    let preload_bytes = u16::from_bytes_at(entry_bytes, 4).with_field("PreloadBytes")? as usize;
+                    let file_name = match ext.trim() {
+                        "" => file_name,
+                        ext => format!("{}.{}", file_name, ext),
+                    };
+                    let path = match dir.trim() {
+                        "" => file_name,
+                        dir => format!("{}/{}", dir, file_name),
+                    };
+                    let entry = VpkEntry {
+                        crc32: u32::from_bytes_at(entry_bytes, 0).with_field("CRC")?,
+                        archive_index: u16::from_bytes_at(entry_bytes, 6).with_field("ArchiveIndex")?,
+                        entry_offset: u32::from_bytes_at(entry_bytes, 8).with_field("EntryOffset")?,
+                        entry_length: u32::from_bytes_at(entry_bytes, 12).with_field("EntryLength")?,
+                        preload: get_slice(tree, ofs + ENTRY_SIZE, preload_bytes).with_field("preload")?.to_vec(),
+                        path: path.clone(),
+                    };
+                    ofs += ENTRY_SIZE + preload_bytes;
+                    entries.insert(normalize_name(&path), entry);
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn dir_path(&self) -> &Path {
+        &self.dir_path
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &VpkEntry> {
+        self.entries.values()
+    }
+
+    pub fn find(&self, path: &str) -> Option<&VpkEntry> {
+        self.entries.get(&normalize_name(path))
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    fn archive_path(&self, archive_index: u16) -> PathBuf {
+        if archive_index == DIR_ARCHIVE_INDEX {
+            return self.dir_path.clone();
+        }
+        chunk_path(&self.dir_path, archive_index)
+    }
+
+    pub fn read_entry(&self, entry: &VpkEntry) -> Result<Vec<u8>> {
+        let mut data = entry.preload.clone();
+
+        if entry.entry_length > 0 {
+            let archive_path = self.archive_path(entry.archive_index);
+            let archive_ofs = if entry.archive_index == DIR_ARCHIVE_INDEX {
+                self.data_ofs + entry.entry_offset as u64
+            } else {
+                entry.entry_offset as u64
+            };
+            let mut f = File::open(&archive_path).with_path(&archive_path)?;
+            data.extend(read_exact_from_file(&mut f, archive_ofs, entry.entry_length as usize).with_path(&archive_path)?);
+        }
+
+        if crc32fast::hash(&data) != entry.crc32 {
+            return Err(Error::malformed(format!("CRC mismatch for {}", entry.path)).with_path(&self.dir_path));
+        }
+
+        Ok(data)
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let entry = self.find(path).ok_or_else(|| Error::not_found(&self.dir_path.join(path)))?;
+        self.read_entry(entry)
+    }
+
+    pub fn extract(&self, path: &str, output_file_path: &Path) -> Result<()> {
+        let data = self.read(path)?;
+        if let Some(parent_dir) = output_file_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        fs::write(output_file_path, data).with_path(output_file_path)
+    }
+}
+
+// Directory -> file stems of one extension, the way the tree nests them.
+type TreeDirs<'a> = BTreeMap<&'a str, Vec<(&'a str, &'a VpkEntry)>>;
+
+#[derive(Debug)]
+pub struct VpkWriter {
+    dir_path: PathBuf,
+    pub chunk_size: u64,
+    pub preload_size: usize,
+    entries: BTreeMap<String, VpkEntry>,
+    chunk: Option<(u16, File, u64)>,
+}
+
+impl VpkWriter {
+    // `pak01`, `pak01.vpk` and `pak01_dir.vpk` all name the same archive set.
+    pub fn new(output_path: &Path) -> Self {
+        let file_name = output_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let lower_name = file_name.to_lowercase();
+        let prefix = if lower_name.ends_with("_dir.vpk") {
+            &file_name[..file_name.len() - "_dir.vpk".len()]
+        } else if lower_name.ends_with(".vpk") {
+            &file_name[..file_name.len() - ".vpk".len()]
+        } else {
+            &file_name
+        };
+
+        Self {
+            dir_path: output_path.with_file_name(format!("{}_dir.vpk", prefix)),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            preload_size: 0,
+            entries: BTreeMap::new(),
+            chunk: None,
+        }
+    }
+
+    pub fn dir_path(&self) -> &Path {
+        &self.dir_path
+    }
+
+    fn write_to_chunk(&mut self, contents: &[u8]) -> Result<(u16, u32)> {
+        let needs_new_chunk = match &self.chunk {
+            Some((_, _, written)) => *written > 0 && *written + contents.len() as u64 > self.chunk_size,
+            None => true,
+        };
+        if needs_new_chunk {
+            let archive_index = self.chunk.as_ref().map_or(0, |(archive_index, _, _)| archive_index + 1);
+            if archive_index == DIR_ARCHIVE_INDEX {
+                return Err(Error::unsupported("too many VPK chunks").with_path(&self.dir_path));
+            }
+            let chunk_path = chunk_path(&self.dir_path, archive_index);
+            if let Some(parent_dir) = chunk_path.parent() {
+                fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+            }
+            self.chunk = Some((archive_index, File::create(&chunk_path).with_path(&chunk_path)?, 0));
+        }
+
+        let (archive_index, f, written) = self.chunk.as_mut().ok_or_else(|| Error::malformed("no open VPK chunk"))?;
+        let entry_offset = u32::try_from(*written)
+            .map_err(|_| Error::unsupported("VPK chunk larger than 4 GiB").with_path(&self.dir_path))?;
+        f.write_all(contents).with_path(&chunk_path(&self.dir_path, *archive_index))?;
+        *written += contents.len() as u64;
+        Ok((*archive_index, entry_offset))
+    }
+
+    fn build_tree(&self) -> Vec<u8> {
+        let mut tree_map: BTreeMap<&str, TreeDirs> = BTreeMap::new();
+        for (rel_path, entry) in &self.entries {
+            let (dir, file_name) = rel_path.rsplit_once('/').unwrap_or((EMPTY_TREE_STR, rel_path));
+            let (name, ext) = file_name.rsplit_once('.').unwrap_or((file_name, EMPTY_TREE_STR));
+            tree_map.entry(ext).or_default().entry(dir).or_default().push((name, entry));
+        }
+
+        let mut tree = Vec::new();
+        for (ext, dirs) in tree_map {
+            tree.extend_from_slice(ext.as_bytes());
+            tree.push(0);
+            for (dir, files) in dirs {
+                tree.extend_from_slice(dir.as_bytes());
+                tree.push(0);
+                for (name, entry) in files {
+                    tree.extend_from_slice(name.as_bytes());
+                    tree.push(0);
+                    tree.extend_from_slice(&entry.crc32.to_le_bytes());
+                    tree.extend_from_slice(&(entry.preload.len() as u16).to_le_bytes());
+                    tree.extend_from_slice(&entry.archive_index.to_le_bytes());
+                    tree.extend_from_slice(&entry.entry_offset.to_le_bytes());
+                    tree.extend_from_slice(&entry.entry_length.to_le_bytes());
+                    tree.extend_from_slice(&ENTRY_TERMINATOR.to_le_bytes());
+                    tree.extend_from_slice(&entry.preload);
+                }
+                tree.push(0);
+            }
+            tree.push(0);
+        }
+        tree.push(0);
+        tree
+    }
+}
+
+impl ContentWriter for VpkWriter {
+    fn add_file(&mut self, rel_path: &str, contents: Vec<u8>) -> Result<()> {
+        let rel_path = safe_rel_path(rel_path)?.to_lowercase();
+        let crc32 = crc32fast::hash(&contents);
+
+        let entry = if contents.len() <= self.preload_size.min(MAX_PRELOAD_SIZE) {
+            VpkEntry {
+                path: rel_path.clone(),
+                crc32,
+                preload: contents,
+                archive_index: DIR_ARCHIVE_INDEX,
+                entry_offset: 0,
+                entry_length: 0,
+            }
+        } else {
+            let entry_length = u32::try_from(contents.len())
+                .map_err(|_| Error::unsupported(format!("{} is larger than 4 GiB", rel_path)))?;
+            let (archive_index, entry_offset) = self.write_to_chunk(&contents)?;
+            VpkEntry {
+                path: rel_path.clone(),
+                crc32,
+                preload: Vec::new(),
+                archive_index,
+                entry_offset,
+                entry_length,
+            }
+        };
+        self.entries.insert(rel_path, entry);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.chunk = None;
+        let tree = self.build_tree();
+
+        let mut out = Vec::with_capacity(V1_HEADER_SIZE + tree.len());
+        out.extend_from_slice(&VPK_SIGNATURE.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+        out.extend_from_slice(&tree);
+
+        if let Some(parent_dir) = self.dir_path.parent() {
+            fs::create_dir_all(parent_dir).with_path(parent_dir)?;
+        }
+        fs::write(&self.dir_path, out).with_path(&self.dir_path)
+    }
+}
@@ -0,0 +1,98 @@
+use std::{fs, path::PathBuf};
+use source_mdl_mats_finder::baseline::Baseline;
+use source_mdl_mats_finder::content::{AssetKind, ContentSet, Referrer};
+use source_mdl_mats_finder::vfs::MemoryFs;
+
+const STOCK_VMT: &[u8] = b"\"LightmappedGeneric\" { \"$basetexture\" \"brick/wall01\" }";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("source_mdl_mats_finder_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn manifest_round_trip() {
+    let dir = temp_dir("baseline_manifest");
+    let manifest_path = dir.join("hl2.txt");
+    fs::write(&manifest_path, format!(
+        "// stock hl2 content\r\nMaterials\\Brick\\Wall01.vmt\t{:08X}\r\n\r\nsound/ambient/wind.wav\r\n",
+        crc32fast::hash(STOCK_VMT),
+    )).unwrap();
+
+    let mut baseline = Baseline::new();
+    baseline.load(&manifest_path).unwrap();
+    assert_eq!(baseline.len(), 2);
+    assert!(baseline.contains("materials/brick/wall01.vmt"));
+    assert_eq!(baseline.to_manifest(), format!(
+        "Materials/Brick/Wall01.vmt\t{:08x}\nsound/ambient/wind.wav\n",
+        crc32fast::hash(STOCK_VMT),
+    ));
+
+    fs::write(&manifest_path, "sound/ambient/wind.wav\tnot-hex\n").unwrap();
+    let err = Baseline::new().add_manifest(&manifest_path).unwrap_err();
+    assert!(err.to_string().contains("bad CRC32 `not-hex` on line 1"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn modified_stock_files_are_still_packed() {
+    let mut baseline = Baseline::new();
+    baseline.insert("materials/brick/wall01.vmt", Some(crc32fast::hash(STOCK_VMT)));
+    baseline.insert("materials/brick/wall02.vmt", Some(crc32fast::hash(STOCK_VMT)));
+    baseline.insert("sound/ambient/wind.wav", None);
+
+    let mut file_system = MemoryFs::new();
+    file_system.insert("materials/brick/wall01.vmt", STOCK_VMT.to_vec());
+    file_system.insert("materials/brick/wall02.vmt", b"\"VertexLitGeneric\" {}".to_vec());
+    file_system.insert("sound/ambient/wind.wav", b"RIFF".to_vec());
+    assert!(baseline.is_stock("MATERIALS/BRICK/WALL01.VMT", &file_system));
+    assert!(!baseline.is_stock("materials/brick/wall02.vmt", &file_system));
+    assert!(baseline.is_stock("sound/ambient/wind.wav", &file_system));
+    assert!(!baseline.is_stock("materials/custom/sign.vmt", &file_system));
+
+    let mut content_set = ContentSet::new();
+    for path in ["materials/brick/wall01.vmt", "materials/brick/wall02.vmt", "sound/ambient/wind.wav"] {
+        content_set.insert(path, AssetKind::from_path(path), Referrer::Map, Some("game".to_string()));
+    }
+    content_set.insert("materials/brick/wall03.vmt", AssetKind::Material, Referrer::Map, None);
+    assert_eq!(content_set.mark_stock(&baseline, &file_system), 2);
+    let to_copy: Vec<&str> = content_set.to_copy().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(to_copy, ["materials/brick/wall02.vmt"]);
+    let missing: Vec<&str> = content_set.missing().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(missing, ["materials/brick/wall03.vmt"]);
+}
+
+#[test]
+fn vpk_baseline_uses_entry_crcs() {
+    let dir = temp_dir("baseline_vpk");
+    let vpk_path = dir.join("hl2_misc_dir.vpk");
+    let mut tree = Vec::new();
+    for s in ["vmt", "materials/brick", "wall01"] {
+        tree.extend_from_slice(s.as_bytes());
+        tree.push(0);
+    }
+    tree.extend_from_slice(&crc32fast::hash(STOCK_VMT).to_le_bytes());
+    tree.extend_from_slice(&(STOCK_VMT.len() as u16).to_le_bytes());
+    tree.extend_from_slice(&0x7fffu16.to_le_bytes());
+    tree.extend_from_slice(&[0; 8]);
+    tree.extend_from_slice(&0xffffu16.to_le_bytes());
+    tree.extend_from_slice(STOCK_VMT);
+    tree.extend_from_slice(&[0, 0, 0]);
+    let mut vpk = Vec::new();
+    for field in [0x55aa1234u32, 1, tree.len() as u32] {
+        vpk.extend_from_slice(&field.to_le_bytes());
+    }
+    vpk.extend_from_slice(&tree);
+    fs::write(&vpk_path, vpk).unwrap();
+
+    let mut baseline = Baseline::new();
+    baseline.load(&vpk_path).unwrap();
+    assert_eq!(baseline.to_manifest(), format!("materials/brick/wall01.vmt\t{:08x}\n", crc32fast::hash(STOCK_VMT)));
+
+    let mut file_system = MemoryFs::new();
+    file_system.insert("materials/brick/wall01.vmt", b"edited".to_vec());
+    assert!(!baseline.is_stock("materials/brick/wall01.vmt", &file_system));
+    fs::remove_dir_all(&dir).unwrap();
+}
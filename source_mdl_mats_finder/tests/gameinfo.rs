@@ -0,0 +1,92 @@
+use std::{fs, path::{Path, PathBuf}};
+use source_mdl_mats_finder::gameinfo::GameInfo;
+
+const GAMEINFO: &str = r#"
+"GameInfo"
+{
+    game "Test Mod"
+    FileSystem
+    {
+        SearchPaths
+        {
+            game+mod        |gameinfo_path|.
+            game            |gameinfo_path|custom/*
+            game            |all_source_engine_paths|hl2/hl2_misc.vpk
+            game            |all_source_engine_paths|hl2
+            platform        |all_source_engine_paths|platform
+        }
+    }
+}
+"#;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("source_mdl_mats_finder_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// An empty version 1 directory file, enough to be mounted.
+fn write_empty_vpk(path: &Path) {
+    let mut vpk = Vec::new();
+    for field in [0x55aa1234u32, 1, 1] {
+        vpk.extend_from_slice(&field.to_le_bytes());
+    }
+    vpk.push(0);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, vpk).unwrap();
+}
+
+fn build_install(root: &Path) -> PathBuf {
+    let mod_dir = root.join("mod");
+    let hl2_dir = root.join("hl2");
+    let custom_dir = mod_dir.join("custom");
+    fs::create_dir_all(custom_dir.join("b_folder")).unwrap();
+    fs::create_dir_all(root.join("platform")).unwrap();
+    fs::write(mod_dir.join("gameinfo.txt"), GAMEINFO).unwrap();
+    write_empty_vpk(&mod_dir.join("pak01_dir.vpk"));
+    write_empty_vpk(&custom_dir.join("a_pack_dir.vpk"));
+    fs::write(custom_dir.join("a_pack_000.vpk"), b"chunk data").unwrap();
+    fs::write(custom_dir.join("c_broken.gma"), b"not a gma").unwrap();
+    write_empty_vpk(&hl2_dir.join("hl2_misc_dir.vpk"));
+    write_empty_vpk(&hl2_dir.join("pak01_dir.vpk"));
+    mod_dir.join("gameinfo.txt")
+}
+
+#[test]
+fn expands_search_paths_in_order() {
+    let root = temp_dir("gameinfo_order");
+    let game_info = GameInfo::open(&build_install(&root)).unwrap();
+    assert_eq!(game_info.game.as_deref(), Some("Test Mod"));
+    assert_eq!(game_info.search_paths.len(), 5);
+    assert!(game_info.search_paths[0].has_id("MOD"));
+    assert_eq!(game_info.resolve_value("|gameinfo_path|."), root.join("mod"));
+    assert_eq!(game_info.resolve_value("|all_source_engine_paths|hl2\\"), root.join("hl2"));
+
+    let search_paths = game_info.mount().unwrap();
+    let layers: Vec<String> = search_paths.layers().iter().map(|layer| layer.describe()).collect();
+    let expected: Vec<String> = [
+        root.join("mod/pak01_dir.vpk"),
+        root.join("mod"),
+        root.join("mod/custom/a_pack_dir.vpk"),
+        root.join("mod/custom/b_folder"),
+        root.join("hl2/hl2_misc_dir.vpk"),
+        root.join("hl2/pak01_dir.vpk"),
+        root.join("hl2"),
+    ].iter().map(|path| path.display().to_string()).collect();
+    assert_eq!(layers, expected);
+
+    assert_eq!(search_paths.skipped().len(), 1);
+    assert!(search_paths.skipped()[0].contains("c_broken.gma"), "{:?}", search_paths.skipped());
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn missing_search_paths_block_is_an_error() {
+    let root = temp_dir("gameinfo_empty");
+    let gameinfo_path = root.join("gameinfo.txt");
+    fs::write(&gameinfo_path, "\"GameInfo\" { game \"Empty\" }").unwrap();
+    let err = GameInfo::open(&gameinfo_path).unwrap_err();
+    assert!(err.to_string().contains("no FileSystem/SearchPaths block"), "{}", err);
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,74 @@
+use std::{fs, path::PathBuf};
+use source_mdl_mats_finder::gma::{GmaArchive, GmaWriter};
+use source_mdl_mats_finder::output::{create_writer, ContentWriter, OutputFormat, OutputOptions};
+
+fn sample_files() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("materials/models/props/crate.vmt", b"\"VertexLitGeneric\" { \"$basetexture\" \"models/props/crate\" }".to_vec()),
+        ("materials/models/props/crate.vtf", (0..=255u8).cycle().take(3000).collect()),
+        ("models/props/crate.mdl", vec![0x49; 700]),
+        ("sound/ambient/wind.wav", vec![7; 5]),
+    ]
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("source_mdl_mats_finder_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_all(writer: &mut dyn ContentWriter) {
+    for (rel_path, contents) in sample_files() {
+        writer.add_file(rel_path, contents).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[test]
+fn gma_round_trip() {
+    let dir = temp_dir("gma");
+    let gma_path = dir.join("out.gma");
+    let mut writer = GmaWriter::new(&gma_path, "Crate addon");
+    writer.add_file("maps/readme.exe", vec![1, 2, 3]).unwrap();
+    write_all(&mut writer);
+    assert_eq!(writer.rejected(), ["maps/readme.exe"]);
+
+    let archive = GmaArchive::open(&gma_path).unwrap();
+    assert_eq!(archive.name(), "Crate addon");
+    assert_eq!(archive.entries().count(), sample_files().len());
+    for (rel_path, contents) in sample_files() {
+        let entry = archive.find(rel_path).unwrap();
+        assert_eq!(entry.crc32, crc32fast::hash(&contents));
+        assert_eq!(entry.size, contents.len() as u64);
+        assert_eq!(archive.read(rel_path).unwrap(), contents);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn workshop_metadata_comes_from_output_options() {
+    let dir = temp_dir("gma_metadata");
+    let gma_path = dir.join("out.gma");
+    let output_options = OutputOptions {
+        title: "Crate addon".to_string(),
+        description: "A \"crate\"".to_string(),
+        addon_type: "model".to_string(),
+        tags: vec!["build".to_string(), "realism".to_string()],
+        ..OutputOptions::default()
+    };
+    write_all(create_writer(OutputFormat::Gma, &gma_path, &output_options).as_mut());
+
+    let gma = fs::read(&gma_path).unwrap();
+    let description = b"{\"description\":\"A \\\"crate\\\"\",\"type\":\"model\",\"tags\":[\"build\",\"realism\"]}\0";
+    assert!(gma.windows(description.len()).any(|window| window == description));
+    assert_eq!(GmaArchive::open(&gma_path).unwrap().entries().count(), sample_files().len());
+
+    // Without a type the writer keeps its map default.
+    let mut writer = GmaWriter::new(&gma_path, "Crate addon");
+    writer.add_file("maps/test.bsp", vec![0; 4]).unwrap();
+    let gma = writer.to_bytes();
+    let description = b"\"type\":\"map\",\"tags\":[]}\0";
+    assert!(gma.windows(description.len()).any(|window| window == description));
+    fs::remove_dir_all(&dir).unwrap();
+}
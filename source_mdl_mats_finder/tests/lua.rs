@@ -0,0 +1,38 @@
+use source_mdl_mats_finder::baseline::Baseline;
+use source_mdl_mats_finder::content::{AssetKind, ContentSet, Referrer};
+use source_mdl_mats_finder::lua::build_resource_lua;
+use source_mdl_mats_finder::vfs::MemoryFs;
+
+fn sample_set() -> ContentSet {
+    let mut content_set = ContentSet::new();
+    let found = || Some("game".to_string());
+    content_set.insert("models/props/crate.mdl", AssetKind::Model, Referrer::Map, found());
+    content_set.insert("models/props/crate.vvd", AssetKind::ModelCompanion, Referrer::Map, found());
+    content_set.insert("materials/models/props/crate.vmt", AssetKind::Material, Referrer::Map, found());
+    content_set.insert("materials/models/props/Crate.vtf", AssetKind::Texture, Referrer::Map, found());
+    content_set.insert("materials/models/props/crate_normal.vtf", AssetKind::Texture, Referrer::Map, found());
+    content_set.insert("sound/ambient/\"wind\".wav", AssetKind::Sound, Referrer::Map, found());
+    content_set.insert("scripts/propdata/crate.txt", AssetKind::Script, Referrer::Map, found());
+    content_set.insert("materials/brick/wall01.vmt", AssetKind::Material, Referrer::Map, found());
+    content_set.insert("materials/models/props/missing.vmt", AssetKind::Material, Referrer::Map, None);
+
+    let mut baseline = Baseline::new();
+    baseline.insert("materials/brick/wall01.vmt", None);
+    content_set.mark_stock(&baseline, &MemoryFs::new());
+    content_set
+}
+
+#[test]
+fn lists_root_files() {
+    assert_eq!(build_resource_lua(&sample_set(), None), concat!(
+        "resource.AddFile(\"models/props/crate.mdl\")\n",
+        "resource.AddFile(\"materials/models/props/crate.vmt\")\n",
+        "resource.AddFile(\"materials/models/props/crate_normal.vtf\")\n",
+        "resource.AddFile(\"sound/ambient/\\\"wind\\\".wav\")\n",
+    ));
+}
+
+#[test]
+fn workshop_id_replaces_the_file_list() {
+    assert_eq!(build_resource_lua(&sample_set(), Some("123456789")), "resource.AddWorkshop(\"123456789\")\n");
+}
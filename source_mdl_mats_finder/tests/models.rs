@@ -0,0 +1,319 @@
+use std::path::Path;
+use source_mdl_mats_finder::content::{AssetKind, ContentSet, Referrer, ResolveContext};
+use source_mdl_mats_finder::finder::{TexturesInfo, resolve_model, resolve_model_skins};
+use source_mdl_mats_finder::vfs::MemoryFs;
+
+const HEADER_SIZE: usize = 408;
+
+// The parts of a studiohdr_t the finder reads, laid out the way studiomdl writes them.
+#[derive(Default)]
+struct Mdl<'a> {
+    name: &'a str,
+    checksum: i32,
+    textures: &'a [&'a str],
+    cd_dirs: &'a [&'a str],
+    skin_families: &'a [&'a [i16]],
+    body_parts: i32,
+    include_models: &'a [&'a str],
+    keyvalues: &'a str,
+}
+
+fn put_i32(buf: &mut [u8], ofs: usize, value: i32) {
+    buf[ofs..ofs + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+impl Mdl<'_> {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut mdl = vec![0u8; HEADER_SIZE];
+        mdl[..4].copy_from_slice(b"IDST");
+        put_i32(&mut mdl, 4, 48);
+        put_i32(&mut mdl, 8, self.checksum);
+        mdl[12..12 + self.name.len()].copy_from_slice(self.name.as_bytes());
+
+        let tex_ofs = mdl.len();
+        mdl.resize(tex_ofs + 64 * self.textures.len(), 0);
+        let cd_ofs = mdl.len();
+        mdl.resize(cd_ofs + 4 * self.cd_dirs.len(), 0);
+        let default_family: Vec<i16> = (0..self.textures.len() as i16).collect();
+        let skin_families = match self.skin_families {
+            [] => vec![default_family.as_slice()],
+            skin_families => skin_families.to_vec(),
+        };
+        let skin_ofs = mdl.len();
+        for texture_id in skin_families.iter().flat_map(|family| family.iter()) {
+            mdl.extend_from_slice(&texture_id.to_le_bytes());
+        }
+        mdl.resize(mdl.len().next_multiple_of(4), 0);
+        let include_ofs = mdl.len();
+        mdl.resize(include_ofs + 8 * self.include_models.len(), 0);
+
+        // mstudiotexture_t names are relative to the record, cdtexture offsets are absolute.
+        for (i, texture) in self.textures.iter().enumerate() {
+            let record_ofs = tex_ofs + 64 * i;
+            let name_ofs = mdl.len();
+            put_i32(&mut mdl, record_ofs, (name_ofs - record_ofs) as i32);
+            mdl.extend_from_slice(texture.as_bytes());
+            mdl.push(0);
+        }
+        for (i, cd_dir) in self.cd_dirs.iter().enumerate() {
+            let name_ofs = mdl.len();
+            put_i32(&mut mdl, cd_ofs + 4 * i, name_ofs as i32);
+            mdl.extend_from_slice(cd_dir.as_bytes());
+            mdl.push(0);
+        }
+        // mstudiomodelgroup_t: an unused label and the model name, relative to the entry.
+        for (i, include_model) in self.include_models.iter().enumerate() {
+            let record_ofs = include_ofs + 8 * i;
+            let name_ofs = mdl.len();
+            put_i32(&mut mdl, record_ofs + 4, (name_ofs - record_ofs) as i32);
+            mdl.extend_from_slice(include_model.as_bytes());
+            mdl.push(0);
+        }
+        if !self.keyvalues.is_empty() {
+            let keyvalue_ofs = mdl.len() as i32;
+            put_i32(&mut mdl, 312, keyvalue_ofs);
+            put_i32(&mut mdl, 316, self.keyvalues.len() as i32 + 1);
+            mdl.extend_from_slice(self.keyvalues.as_bytes());
+            mdl.push(0);
+        }
+
+        for (ofs, value) in [
+            (204, self.textures.len()),
+            (208, tex_ofs),
+            (212, self.cd_dirs.len()),
+            (216, cd_ofs),
+            (220, skin_families[0].len()),
+            (224, skin_families.len()),
+            (228, skin_ofs),
+            (232, self.body_parts as usize),
+            (336, self.include_models.len()),
+            (340, include_ofs),
+        ] {
+            put_i32(&mut mdl, ofs, value as i32);
+        }
+        let length = mdl.len() as i32;
+        put_i32(&mut mdl, 76, length);
+        mdl
+    }
+}
+
+fn vmt(base_texture: &str) -> Vec<u8> {
+    format!("\"VertexLitGeneric\" {{ \"$basetexture\" \"{}\" }}", base_texture).into_bytes()
+}
+
+// A .vvd, .vtx or .phy header with the model checksum where that format keeps it.
+fn companion(suffix: &str, checksum: i32) -> Vec<u8> {
+    let checksum_ofs = match suffix {
+        suffix if suffix.ends_with(".vtx") => 16,
+        ".phy" => 12,
+        _ => 8,
+    };
+    let mut header = vec![0u8; 20];
+    put_i32(&mut header, checksum_ofs, checksum);
+    header
+}
+
+fn paths(content_set: &ContentSet) -> Vec<&str> {
+    let mut paths: Vec<&str> = content_set.iter().map(|entry| entry.path.as_str()).collect();
+    paths.sort();
+    paths
+}
+
+const SKINNED_CRATE: Mdl = Mdl {
+    name: "props/crate.mdl",
+    checksum: 1234,
+    textures: &["crate", "metal", "crate_red", "crate_blue"],
+    cd_dirs: &["models/props/"],
+    skin_families: &[&[0, 1], &[2, 1], &[3, 1]],
+    body_parts: 0,
+    include_models: &[],
+    keyvalues: "",
+};
+
+fn skinned_crate_fs() -> MemoryFs {
+    let mut file_system = MemoryFs::new();
+    file_system.insert("models/props/crate.mdl", SKINNED_CRATE.to_bytes());
+    for texture in SKINNED_CRATE.textures {
+        file_system.insert(&format!("materials/models/props/{}.vmt", texture), vmt(&format!("models/props/{}", texture)));
+    }
+    file_system
+}
+
+#[test]
+fn skin_families_pick_the_textures() {
+    let textures_info = TexturesInfo::from_bytes(SKINNED_CRATE.to_bytes(), Path::new("models/props/crate.mdl")).unwrap();
+    assert_eq!(textures_info.skin_families(), [vec![0, 1], vec![2, 1], vec![3, 1]]);
+    assert_eq!(textures_info.skin_textures(Some(&[1])), ["crate_red", "metal"]);
+    assert_eq!(textures_info.skin_textures(Some(&[2, 0])), ["crate_blue", "metal", "crate"]);
+    // Like the engine, an out of range skin draws with the default skin.
+    assert_eq!(textures_info.skin_textures(Some(&[9])), ["crate", "metal"]);
+    assert_eq!(textures_info.skin_textures(None), ["crate", "metal", "crate_red", "crate_blue"]);
+}
+
+#[test]
+fn later_skins_add_their_materials() {
+    let file_system = skinned_crate_fs();
+    let mut context = ResolveContext::new();
+    let mut content_set = ContentSet::new();
+    let mut resolve = |skins: Option<&[i32]>, content_set: &mut ContentSet| {
+        let referrer = Referrer::StaticProp { index: 0 };
+        resolve_model_skins(&file_system, Path::new("models/props/crate.mdl"), referrer, skins, &mut context, content_set).unwrap();
+        paths(content_set).iter().filter(|path| path.ends_with(".vmt")).map(|path| path.to_string()).collect::<Vec<String>>()
+    };
+
+    assert_eq!(resolve(Some(&[1]), &mut content_set), ["materials/models/props/crate_red.vmt", "materials/models/props/metal.vmt"]);
+    assert_eq!(resolve(Some(&[1]), &mut content_set).len(), 2);
+    assert_eq!(resolve(Some(&[2]), &mut content_set), [
+        "materials/models/props/crate_blue.vmt",
+        "materials/models/props/crate_red.vmt",
+        "materials/models/props/metal.vmt",
+    ]);
+    assert_eq!(resolve(None, &mut content_set).len(), 4);
+    assert!(content_set.contains("materials/models/props/crate.vtf"));
+    assert_eq!(content_set.missing().count(), 4);
+}
+
+#[test]
+fn companions_are_checked_against_the_model() {
+    let crate_mdl = Mdl { name: "props/crate.mdl", checksum: 1234, body_parts: 1, ..Mdl::default() };
+    let mut file_system = MemoryFs::new();
+    // The model was moved after compiling, so its companions are looked up beside the new path.
+    file_system.insert("models/moved/crate.mdl", crate_mdl.to_bytes());
+    file_system.insert("models/moved/crate.vvd", companion(".vvd", 1234));
+    file_system.insert("models/moved/crate.phy", companion(".phy", 1234));
+    file_system.insert("models/moved/crate.dx90.vtx", companion(".dx90.vtx", 99));
+    file_system.insert("models/moved/crate.sw.vtx", companion(".sw.vtx", 1234));
+    file_system.insert("models/moved/crate.ani", vec![0; 5]);
+    file_system.insert("models/moved/crate_gibs.vvd", companion(".vvd", 1234));
+    file_system.insert("models/props/crate.vvd", companion(".vvd", 1234));
+
+    let mut content_set = ContentSet::new();
+    resolve_model(&file_system, Path::new("models/moved/crate.mdl"), Referrer::Map, &mut ResolveContext::new(), &mut content_set).unwrap();
+    assert_eq!(paths(&content_set), [
+        "models/moved/crate.ani",
+        "models/moved/crate.dx90.vtx",
+        "models/moved/crate.mdl",
+        "models/moved/crate.phy",
+        "models/moved/crate.sw.vtx",
+        "models/moved/crate.vvd",
+    ]);
+    let to_copy: Vec<&str> = content_set.to_copy().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(to_copy, ["models/moved/crate.mdl", "models/moved/crate.vvd", "models/moved/crate.phy", "models/moved/crate.sw.vtx"]);
+    let stale: Vec<&str> = content_set.stale().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(stale, ["models/moved/crate.ani", "models/moved/crate.dx90.vtx"]);
+    let broken: Vec<&str> = content_set.broken().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(broken, ["models/moved/crate.ani"]);
+}
+
+#[test]
+fn missing_vertex_data_is_reported() {
+    let barrel_mdl = Mdl { name: "props/barrel.mdl", checksum: 1, body_parts: 1, ..Mdl::default() };
+    let mut file_system = MemoryFs::new();
+    file_system.insert("models/props/barrel.mdl", barrel_mdl.to_bytes());
+    file_system.insert("models/props/barrel.phy", companion(".phy", 1));
+
+    let mut content_set = ContentSet::new();
+    resolve_model(&file_system, Path::new("models/props/barrel.mdl"), Referrer::Map, &mut ResolveContext::new(), &mut content_set).unwrap();
+    let missing: Vec<&str> = content_set.missing().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(missing, ["models/props/barrel.vvd", "models/props/barrel.dx90.vtx"]);
+    let referrer = Referrer::Asset("models/props/barrel.mdl".to_string());
+    assert!(content_set.missing().all(|entry| entry.referenced_by == [referrer.clone()]));
+}
+
+#[test]
+fn include_model_cycles_terminate() {
+    let mut file_system = MemoryFs::new();
+    let player = Mdl { name: "player/male.mdl", textures: &["male_face"], cd_dirs: &["models/player/"], include_models: &["models/player/male_anims.mdl"], ..Mdl::default() };
+    // Animation models name the model that includes them back, and the prefix is optional.
+    let anims = Mdl { name: "player/male_anims.mdl", include_models: &["player/male.mdl", "models/player/shared_gestures.mdl"], ..Mdl::default() };
+    file_system.insert("models/player/male.mdl", player.to_bytes());
+    file_system.insert("models/player/male_anims.mdl", anims.to_bytes());
+    file_system.insert("materials/models/player/male_face.vmt", vmt("models/player/male_face"));
+
+    let mut content_set = ContentSet::new();
+    resolve_model(&file_system, Path::new("models/player/male.mdl"), Referrer::Map, &mut ResolveContext::new(), &mut content_set).unwrap();
+    assert_eq!(paths(&content_set), [
+        "materials/models/player/male_face.vmt",
+        "materials/models/player/male_face.vtf",
+        "models/player/male.mdl",
+        "models/player/male_anims.mdl",
+        "models/player/shared_gestures.mdl",
+    ]);
+    let male = content_set.get("models/player/male.mdl").unwrap();
+    assert_eq!(male.referenced_by, [Referrer::Map, Referrer::Asset("models/player/male_anims.mdl".to_string())]);
+    let missing: Vec<&str> = content_set.missing().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(missing, ["materials/models/player/male_face.vtf", "models/player/shared_gestures.mdl"]);
+    assert!(content_set.errors().is_empty());
+}
+
+const PROPDATA_MANIFEST: &str = "PropDataFiles { file \"scripts/propdata/base.txt\" file \"scripts/propdata/wood.txt\" file \"scripts/propdata/gone.txt\" }";
+const BASE_PROPDATA: &str = "PropData { \"Wooden.Medium\" { health 50 breakable_model \"WoodChunks\" } }";
+const WOOD_PROPDATA: &str = "PropData { BreakableModels { WoodChunks { \"models/gibs/wood_gib01a.mdl\" \"1\" \"models/gibs/wood_gib01b.mdl\" \"1\" } } }";
+
+#[test]
+fn gibs_are_collected_recursively() {
+    let mut file_system = MemoryFs::new();
+    file_system.insert("scripts/propdata.txt", PROPDATA_MANIFEST.as_bytes().to_vec());
+    file_system.insert("scripts/propdata/base.txt", BASE_PROPDATA.as_bytes().to_vec());
+    file_system.insert("scripts/propdata/wood.txt", WOOD_PROPDATA.as_bytes().to_vec());
+    let crate_mdl = Mdl {
+        name: "props/crate.mdl",
+        keyvalues: "mdlkeyvalue { prop_data { base \"Wooden.Medium\" } physgun_interactions { onbreak \"models/gibs/crate_lid.mdl\" } }",
+        ..Mdl::default()
+    };
+    // A gib breaking into the model that made it must not loop.
+    let gib_mdl = Mdl { name: "gibs/wood_gib01a.mdl", keyvalues: "prop_data { breakable_model \"models/props/crate.mdl\" }", ..Mdl::default() };
+    file_system.insert("models/props/crate.mdl", crate_mdl.to_bytes());
+    file_system.insert("models/gibs/wood_gib01a.mdl", gib_mdl.to_bytes());
+    file_system.insert("models/gibs/wood_gib01b.mdl", Mdl { name: "gibs/wood_gib01b.mdl", ..Mdl::default() }.to_bytes());
+
+    let mut content_set = ContentSet::new();
+    resolve_model(&file_system, Path::new("models/props/crate.mdl"), Referrer::Map, &mut ResolveContext::new(), &mut content_set).unwrap();
+    assert_eq!(paths(&content_set), [
+        "models/gibs/crate_lid.mdl",
+        "models/gibs/wood_gib01a.mdl",
+        "models/gibs/wood_gib01b.mdl",
+        "models/props/crate.mdl",
+        "scripts/propdata/base.txt",
+        "scripts/propdata/wood.txt",
+    ]);
+    assert_eq!(content_set.get("scripts/propdata/wood.txt").unwrap().kind, AssetKind::Script);
+    let crate_referrer = Referrer::Asset("models/props/crate.mdl".to_string());
+    assert_eq!(content_set.get("models/gibs/wood_gib01b.mdl").unwrap().referenced_by, [crate_referrer]);
+    let missing: Vec<&str> = content_set.missing().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(missing, ["models/gibs/crate_lid.mdl"]);
+    assert!(content_set.errors().is_empty());
+}
+
+#[test]
+fn propdata_errors_are_recorded_once() {
+    let mut file_system = MemoryFs::new();
+    file_system.insert("scripts/propdata.txt", b"PropDataFiles { file".to_vec());
+    for name in ["crate", "barrel"] {
+        let mdl = Mdl { name: &format!("props/{}.mdl", name), keyvalues: "prop_data { base \"Wooden.Medium\" }", ..Mdl::default() }.to_bytes();
+        file_system.insert(&format!("models/props/{}.mdl", name), mdl);
+    }
+
+    let mut context = ResolveContext::new();
+    let mut content_set = ContentSet::new();
+    for name in ["crate", "barrel"] {
+        let mdl_path = format!("models/props/{}.mdl", name);
+        resolve_model(&file_system, Path::new(&mdl_path), Referrer::Map, &mut context, &mut content_set).unwrap();
+    }
+    assert_eq!(content_set.len(), 2);
+    assert_eq!(content_set.errors().len(), 1);
+    assert!(content_set.errors()[0].contains("scripts/propdata.txt"), "{:?}", content_set.errors());
+}
+
+#[test]
+fn unparsable_keyvalues_mark_the_model_broken() {
+    let mut file_system = MemoryFs::new();
+    let crate_mdl = Mdl { name: "props/crate.mdl", textures: &["crate"], cd_dirs: &["models/props/"], keyvalues: "prop_data { base", ..Mdl::default() };
+    file_system.insert("models/props/crate.mdl", crate_mdl.to_bytes());
+
+    let mut content_set = ContentSet::new();
+    resolve_model(&file_system, Path::new("models/props/crate.mdl"), Referrer::Map, &mut ResolveContext::new(), &mut content_set).unwrap();
+    assert!(content_set.contains("materials/models/props/crate.vmt"));
+    let broken: Vec<&str> = content_set.broken().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(broken, ["models/props/crate.mdl"]);
+}
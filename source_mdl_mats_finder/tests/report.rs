@@ -0,0 +1,53 @@
+use std::path::Path;
+use simple_utils::error::Error;
+use source_mdl_mats_finder::baseline::Baseline;
+use source_mdl_mats_finder::content::{AssetKind, ContentSet, Referrer};
+use source_mdl_mats_finder::report::{MissingReport, ReportFormat};
+use source_mdl_mats_finder::vfs::MemoryFs;
+
+fn sample_set() -> ContentSet {
+    let mut content_set = ContentSet::new();
+    let prop = Referrer::Entity { index: 3, classname: "prop_dynamic".to_string() };
+    content_set.insert("models/props/crate.mdl", AssetKind::Model, prop.clone(), Some("game".to_string()));
+    content_set.insert("models/props/crate.vvd", AssetKind::ModelCompanion, Referrer::Asset("models/props/crate.mdl".to_string()), Some("game".to_string()));
+    content_set.insert("materials/models/props/crate.vmt", AssetKind::Material, Referrer::Asset("models/props/crate.mdl".to_string()), None);
+    content_set.insert("sound/ambient/\"wind\".wav", AssetKind::Sound, prop, None);
+    content_set.insert("materials/brick/wall01.vmt", AssetKind::Material, Referrer::Map, Some("hl2".to_string()));
+    content_set.mark_stale("models/props/crate.vvd");
+    content_set.mark_broken("models/props/crate.mdl", &Error::malformed("bad keyvalues"));
+    content_set.add_error(&Error::not_found(Path::new("scripts/propdata.txt")));
+
+    let mut baseline = Baseline::new();
+    baseline.insert("materials/brick/wall01.vmt", None);
+    content_set.mark_stock(&baseline, &MemoryFs::new());
+    content_set
+}
+
+#[test]
+fn json_report() {
+    let report = MissingReport::new("maps/test.bsp", &sample_set());
+    assert_eq!(report.missing_count(), 2);
+    assert_eq!(report.render(ReportFormat::Json), concat!(
+        "{\"source\":\"maps/test.bsp\",\"missing_count\":2,",
+        "\"groups\":[",
+        "{\"referrer\":{\"type\":\"entity\",\"index\":3,\"classname\":\"prop_dynamic\"},\"missing\":[{\"path\":\"sound/ambient/\\\"wind\\\".wav\",\"kind\":\"sound\"}]},",
+        "{\"referrer\":{\"type\":\"model\",\"path\":\"models/props/crate.mdl\"},\"missing\":[{\"path\":\"materials/models/props/crate.vmt\",\"kind\":\"material\"}]}",
+        "],",
+        "\"found\":[{\"search_path\":\"game\",\"files\":[\"models/props/crate.mdl\"]}],",
+        "\"stock\":[\"materials/brick/wall01.vmt\"],",
+        "\"stale\":[\"models/props/crate.vvd\"],",
+        "\"broken\":[{\"path\":\"models/props/crate.mdl\",\"error\":\"malformed data: bad keyvalues\"}],",
+        "\"errors\":[\"file not found (scripts/propdata.txt)\"]}",
+    ));
+}
+
+#[test]
+fn text_report_lists_every_section() {
+    let text = MissingReport::new("maps/test.bsp", &sample_set()).render(ReportFormat::Text);
+    assert!(text.starts_with("Missing content for maps/test.bsp: 2 file(s)\n"));
+    assert!(text.contains("\nentity #3 (prop_dynamic):\n  sound            sound/ambient/\"wind\".wav\n"));
+    assert!(text.contains("\nStock content (excluded): 1 file(s)\n  materials/brick/wall01.vmt\n"));
+    assert!(text.contains("\nStale model companions (checksum mismatch, not copied): 1 file(s)\n  models/props/crate.vvd\n"));
+    assert!(text.contains("\n  models/props/crate.mdl: malformed data: bad keyvalues\n"));
+    assert!(text.contains("\nResolve errors: 1\n  file not found (scripts/propdata.txt)\n"));
+}
@@ -0,0 +1,152 @@
+use std::{fs, path::PathBuf};
+use source_mdl_mats_finder::output::ContentWriter;
+use source_mdl_mats_finder::vpk::{VpkArchive, VpkWriter};
+
+const VMT: &[u8] = b"\"LightmappedGeneric\" { \"$basetexture\" \"test/wall\" }";
+const VTF: &[u8] = b"VTF\0 texture data kept in the directory file";
+const TXT: &[u8] = b"stored in pak01_000.vpk";
+
+fn sample_files() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("materials/models/props/crate.vmt", b"\"VertexLitGeneric\" { \"$basetexture\" \"models/props/crate\" }".to_vec()),
+        ("materials/models/props/crate.vtf", (0..=255u8).cycle().take(3000).collect()),
+        ("models/props/crate.mdl", vec![0x49; 700]),
+        ("sound/ambient/wind.wav", vec![7; 5]),
+    ]
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("source_mdl_mats_finder_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_all(writer: &mut dyn ContentWriter) {
+    for (rel_path, contents) in sample_files() {
+        writer.add_file(rel_path, contents).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn push_str(tree: &mut Vec<u8>, s: &str) {
+    tree.extend_from_slice(s.as_bytes());
+    tree.push(0);
+}
+
+fn push_entry(tree: &mut Vec<u8>, contents: &[u8], preload: &[u8], archive_index: u16, entry_offset: u32, entry_length: u32) {
+    tree.extend_from_slice(&crc32fast::hash(contents).to_le_bytes());
+    tree.extend_from_slice(&(preload.len() as u16).to_le_bytes());
+    tree.extend_from_slice(&archive_index.to_le_bytes());
+    tree.extend_from_slice(&entry_offset.to_le_bytes());
+    tree.extend_from_slice(&entry_length.to_le_bytes());
+    tree.extend_from_slice(&0xffffu16.to_le_bytes());
+    tree.extend_from_slice(preload);
+}
+
+// A version 2 directory file: one preload-only entry, one split between preload and the data
+// after the tree, one in a numbered chunk, and one whose stored CRC does not match.
+fn write_v2_vpk(dir: &std::path::Path) -> PathBuf {
+    let mut tree = Vec::new();
+    push_str(&mut tree, "vmt");
+    push_str(&mut tree, "materials/Test");
+    push_str(&mut tree, "Wall");
+    push_entry(&mut tree, VMT, VMT, 0x7fff, 0, 0);
+    push_str(&mut tree, "");
+    push_str(&mut tree, "");
+    push_str(&mut tree, "vtf");
+    push_str(&mut tree, "materials/test");
+    push_str(&mut tree, "wall");
+    push_entry(&mut tree, VTF, &VTF[..4], 0x7fff, 0, (VTF.len() - 4) as u32);
+    push_str(&mut tree, "");
+    push_str(&mut tree, "");
+    push_str(&mut tree, "txt");
+    push_str(&mut tree, " ");
+    push_str(&mut tree, "readme");
+    push_entry(&mut tree, TXT, &[], 0, 4, TXT.len() as u32);
+    push_str(&mut tree, "");
+    push_str(&mut tree, "");
+    push_str(&mut tree, "dat");
+    push_str(&mut tree, "data");
+    push_str(&mut tree, "bad");
+    push_entry(&mut tree, b"something else", b"stored", 0x7fff, 0, 0);
+    push_str(&mut tree, "");
+    push_str(&mut tree, "");
+    push_str(&mut tree, "");
+
+    let file_data = &VTF[4..];
+    let mut vpk = Vec::new();
+    for field in [0x55aa1234u32, 2, tree.len() as u32, file_data.len() as u32, 0, 0, 0] {
+        vpk.extend_from_slice(&field.to_le_bytes());
+    }
+    vpk.extend_from_slice(&tree);
+    vpk.extend_from_slice(file_data);
+
+    let dir_path = dir.join("pak01_dir.vpk");
+    fs::write(&dir_path, vpk).unwrap();
+    fs::write(dir.join("pak01_000.vpk"), [b"pad!".as_slice(), TXT].concat()).unwrap();
+    dir_path
+}
+
+#[test]
+fn reads_v2_entries() {
+    let dir = temp_dir("vpk_v2");
+    let archive = VpkArchive::open(&write_v2_vpk(&dir)).unwrap();
+    assert_eq!(archive.version(), 2);
+    assert_eq!(archive.entries().count(), 4);
+
+    assert_eq!(archive.read("materials/test/wall.vmt").unwrap(), VMT);
+    assert_eq!(archive.find("MATERIALS\\TEST\\WALL.VMT").unwrap().path, "materials/Test/Wall.vmt");
+    assert_eq!(archive.read("materials/test/wall.vtf").unwrap(), VTF);
+    assert_eq!(archive.find("materials/test/wall.vtf").unwrap().size(), VTF.len() as u64);
+    assert_eq!(archive.read("readme.txt").unwrap(), TXT);
+    assert!(!archive.contains("materials/test/missing.vmt"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn crc_mismatch_is_an_error() {
+    let dir = temp_dir("vpk_crc");
+    let archive = VpkArchive::open(&write_v2_vpk(&dir)).unwrap();
+    let err = archive.read("data/bad.dat").unwrap_err();
+    assert!(err.to_string().contains("CRC mismatch for data/bad.dat"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_unknown_versions() {
+    let dir = temp_dir("vpk_version");
+    let dir_path = dir.join("pak01_dir.vpk");
+    let mut vpk = Vec::new();
+    for field in [0x55aa1234u32, 3, 1] {
+        vpk.extend_from_slice(&field.to_le_bytes());
+    }
+    vpk.push(0);
+    fs::write(&dir_path, vpk).unwrap();
+    assert!(VpkArchive::open(&dir_path).unwrap_err().to_string().contains("VPK version 3"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn vpk_round_trip() {
+    let dir = temp_dir("vpk");
+    let mut writer = VpkWriter::new(&dir.join("pak01.vpk"));
+    // Small files go into the directory file, the rest spread over several chunks.
+    writer.preload_size = 100;
+    writer.chunk_size = 1024;
+    write_all(&mut writer);
+    let dir_path = writer.dir_path().to_path_buf();
+    assert!(dir_path.ends_with("pak01_dir.vpk"));
+    assert!(dir.join("pak01_001.vpk").is_file());
+
+    let archive = VpkArchive::open(&dir_path).unwrap();
+    assert_eq!(archive.entries().count(), sample_files().len());
+    for (rel_path, contents) in sample_files() {
+        let entry = archive.find(rel_path).unwrap();
+        assert_eq!(entry.crc32, crc32fast::hash(&contents));
+        assert_eq!(entry.size(), contents.len() as u64);
+        assert_eq!(archive.read(rel_path).unwrap(), contents);
+    }
+    assert!(!archive.find("sound/ambient/wind.wav").unwrap().preload.is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
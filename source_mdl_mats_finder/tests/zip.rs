@@ -0,0 +1,62 @@
+use std::{fs, path::PathBuf};
+use simple_utils::zip::{ZipArchive, ZipWriter};
+use source_mdl_mats_finder::output::{ContentWriter, ZipFileWriter};
+
+fn sample_files() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("materials/models/props/crate.vmt", b"\"VertexLitGeneric\" { \"$basetexture\" \"models/props/crate\" }".to_vec()),
+        ("materials/models/props/crate.vtf", (0..=255u8).cycle().take(3000).collect()),
+        ("models/props/crate.mdl", vec![0x49; 700]),
+        ("sound/ambient/wind.wav", vec![7; 5]),
+    ]
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("source_mdl_mats_finder_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_all(writer: &mut dyn ContentWriter) {
+    for (rel_path, contents) in sample_files() {
+        writer.add_file(rel_path, contents).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[test]
+fn zip_round_trip() {
+    for deflate in [false, true] {
+        let dir = temp_dir(&format!("zip_{}", deflate));
+        let zip_path = dir.join("out.zip");
+        let mut writer = ZipFileWriter::new(&zip_path);
+        writer.deflate = deflate;
+        write_all(&mut writer);
+
+        let archive = ZipArchive::from_file(&zip_path).unwrap();
+        assert_eq!(archive.entries().len(), sample_files().len());
+        for (rel_path, contents) in sample_files() {
+            let entry = archive.find(rel_path).unwrap();
+            assert_eq!(entry.crc32, crc32fast::hash(&contents));
+            assert_eq!(entry.size as usize, contents.len());
+            assert_eq!(archive.read(entry).unwrap(), contents);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn zip64_limits_are_errors() {
+    let mut writer = ZipWriter::new();
+    for i in 0..u16::MAX as usize - 1 {
+        writer.add_file(&format!("materials/{}.vmt", i), b"").unwrap();
+    }
+    let err = writer.add_file("materials/one_too_many.vmt", b"").unwrap_err();
+    assert!(err.to_string().contains("65535 is past the zip limit of 65534 and Zip64 is not supported"), "{}", err);
+    let archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+    assert_eq!(archive.entries().len(), u16::MAX as usize - 1);
+
+    let err = ZipWriter::new().add_file(&"a".repeat(u16::MAX as usize + 1), b"").unwrap_err();
+    assert!(err.to_string().contains("past the zip limit of 65535"), "{}", err);
+}
@@ -1,25 +1,143 @@
 use std::env;
 use std::path::Path;
+use source_mdl_mats_finder::baseline::Baseline;
 use source_mdl_mats_finder::finder::TexturesInfo;
+use source_mdl_mats_finder::output::{OutputOptions, OutputFormat, create_writer};
+use source_mdl_mats_finder::content::{ContentSet, Referrer, ResolveContext};
+use source_mdl_mats_finder::report::CopyPlan;
+use source_mdl_mats_finder::search::SearchPaths;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let mut positional: Vec<&String> = Vec::new();
+    let mut baseline_paths: Vec<&String> = Vec::new();
+    let mut addon_dirs: Vec<&String> = Vec::new();
+    let mut output_format = OutputFormat::Dir;
+    let mut output_options = OutputOptions { addon_type: "model".to_string(), ..OutputOptions::default() };
+    let mut dry_run = false;
+    let mut args_iter = args.iter().skip(1);
 
-    if args.len() != 4 {
-        eprintln!("Usage: {} <find_path> <output_path> <mdl_file_path>", args[0]);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--baseline" => baseline_paths.extend(args_iter.next()),
+            "--addons" => addon_dirs.extend(args_iter.next()),
+            "--format" => {
+                output_format = match args_iter.next().and_then(|name| OutputFormat::from_name(name)) {
+                    Some(r) => r,
+                    None => {
+                        eprintln!("--format expects `dir`, `gma`, `vpk` or `zip`");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--title" => output_options.title = args_iter.next().cloned().unwrap_or_default(),
+            "--description" => output_options.description = args_iter.next().cloned().unwrap_or_default(),
+            "--deflate" => output_options.deflate = true,
+            "--author" => output_options.author = args_iter.next().cloned().unwrap_or_default(),
+            "--tag" => output_options.tags.extend(args_iter.next().cloned()),
+            "--chunk-size-mb" => {
+                output_options.chunk_size = match args_iter.next().and_then(|size| size.parse::<u64>().ok()) {
+                    Some(size_mb) if size_mb > 0 => size_mb * 1024 * 1024,
+                    _ => {
+                        eprintln!("--chunk-size-mb expects a positive number");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--preload-size" => {
+                output_options.preload_size = match args_iter.next().and_then(|size| size.parse::<usize>().ok()) {
+                    Some(r) => r,
+                    None => {
+                        eprintln!("--preload-size expects a byte count");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.len() != 3 {
+        eprintln!("Usage: {} [--dry-run] [--baseline <vpk|manifest>]... [--addons <gma_dir>]... [--format <dir|gma|vpk|zip>] [--deflate] [--title <addon_title>] [--description <text>] [--author <name>] [--tag <workshop_tag>]... [--chunk-size-mb <mb>] [--preload-size <bytes>] <find_path|gameinfo.txt> <output_path> <mdl_file_path>", args[0]);
         std::process::exit(1);
     }
 
-    let find_path = Path::new(&args[1]);
-    let output_path = Path::new(&args[2]);
-    let mdl_file_path = Path::new(&args[3]);
+    let find_path = Path::new(positional[0]);
+    let output_path = Path::new(positional[1]);
+    let mdl_file_path = Path::new(positional[2]);
+
+    if output_options.title.is_empty() {
+        output_options.title = mdl_file_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    }
 
     let texture_info = match TexturesInfo::new(mdl_file_path) {
-        Some(r) => r,
-        None => {
-            eprintln!("Не удалось создать TexturesInfo!");
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{}", err);
             std::process::exit(1);
         }
     };
-    texture_info.download(find_path, output_path);
-}
\ No newline at end of file
+
+    let mut search_paths = match SearchPaths::open(find_path) {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    for addon_dir in addon_dirs {
+        if let Err(err) = search_paths.add_gma_dir(Path::new(addon_dir)) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+    for skipped in search_paths.skipped() {
+        eprintln!("Skipped unreadable archive: {}", skipped);
+    }
+
+    let mut baseline = Baseline::new();
+    for baseline_path in baseline_paths {
+        if let Err(err) = baseline.load(Path::new(baseline_path)) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if dry_run {
+        let mut content_set = ContentSet::new();
+        if let Err(err) = texture_info.resolve(&search_paths, &Referrer::Input, &mut ResolveContext::new(), &mut content_set) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        content_set.mark_stock(&baseline, &search_paths);
+        print_problems(&content_set);
+        let mut plan = CopyPlan::new(&content_set, &search_paths);
+        for rejected_path in plan.reject(output_format) {
+            eprintln!("Not allowed in the output format, would skip: {}", rejected_path);
+        }
+        println!("{}", plan.to_text());
+        return;
+    }
+
+    let mut writer = create_writer(output_format, output_path, &output_options);
+    match texture_info.download(&search_paths, &baseline, writer.as_mut()) {
+        Ok(content_set) => print_problems(&content_set),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+    for rejected_path in writer.rejected() {
+        eprintln!("Not allowed in the output format, skipped: {}", rejected_path);
+    }
+}
+
+fn print_problems(content_set: &ContentSet) {
+    for err in content_set.errors() {
+        eprintln!("{}", err);
+    }
+    for entry in content_set.broken() {
+        eprintln!("Broken {}: {}", entry.path, entry.error.as_deref().unwrap_or_default());
+    }
+}